@@ -0,0 +1,34 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+
+use crate::{error::AppError, AppState};
+
+// proof the request carries `Authorization: Bearer <ADMIN_TOKEN>`; without a configured
+// token the admin operations are off altogether
+pub struct Admin;
+
+// compares every byte so the time taken doesn't tell how much of the token matched
+fn same_token(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len() && given.iter().zip(expected).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for Admin {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Some(expected) = &state.config.admin_token else {
+            return Err(AppError::Forbidden("Admin operations are disabled, ADMIN_TOKEN isn't set".to_string()));
+        };
+        let given = parts.headers.get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        match given {
+            Some(token) if same_token(token.as_bytes(), expected.as_bytes()) => Ok(Admin),
+            _ => Err(AppError::Unauthorized("Admin token missing or wrong".to_string()))
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use mongodb::{bson::{doc, DateTime as BsonDateTime, Document}, options::FindOneOptions};
+use serde::Deserialize;
+
+use crate::AppState;
+
+#[derive(Deserialize)]
+struct DateEntity {
+    date: BsonDateTime
+}
+
+impl AppState {
+    // `filter` narrowed to the most recent `AGG_MAX_DOCS` measurements it matches, and whether
+    // it had to be (the statistics over it are then approximate); one indexed lookup of the
+    // first measurement past the cap
+    pub async fn capped(&self, mut filter: Document) -> mongodb::error::Result<(Document, bool)> {
+        let options = FindOneOptions::builder()
+            .sort(doc! { "date": -1 })
+            .skip(self.config.agg_max_docs)
+            .projection(doc! { "date": 1 })
+            .build();
+        let past_cap = self.timed("find_one", &filter, self.collection.clone_with_type::<DateEntity>().find_one(filter.clone(), self.bounded(options))).await?;
+        let Some(past_cap) = past_cap else {
+            return Ok((filter, false));
+        };
+        // measurements sharing the cutoff's timestamp are left out with it, the result may
+        // fall a few short of the cap but never over it
+        let mut range = filter.get_document("date").cloned().unwrap_or_default();
+        range.insert("$gt", past_cap.date);
+        filter.insert("date", range);
+        Ok((filter, true))
+    }
+}
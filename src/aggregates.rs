@@ -0,0 +1,110 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use mongodb::{bson::{doc, DateTime as BsonDateTime}, options::{FindOptions, ReplaceOptions}};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::AppError, AppState, WheightMeasurementDateEntity};
+
+// one document per calendar day (UTC) with measurements, keyed by the day itself
+#[derive(Serialize, Deserialize)]
+pub struct DailyAggregateEntity {
+    pub _id: String,
+    // start of the day
+    pub date: BsonDateTime,
+    pub count: i64,
+    pub avg_wheight_kg: f32,
+    pub min_wheight_kg: f32,
+    pub max_wheight_kg: f32,
+    // the day's last measurement
+    pub last_wheight_kg: f32,
+    pub computed_at: BsonDateTime
+}
+
+pub fn day_start(day: NaiveDate) -> DateTime<Utc> {
+    day.and_hms_opt(0, 0, 0).unwrap().and_utc()
+}
+
+// recomputes the aggregates every `interval`; the first run (no watermark yet) covers the
+// whole history, later ones only the days of measurements written since the previous run
+pub async fn run(state: AppState, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    let mut watermark: Option<DateTime<Utc>> = None;
+    loop {
+        ticker.tick().await;
+        // taken before reading so writes racing with this run are picked up by the next one
+        let started = Utc::now();
+        match refresh(&state, watermark).await {
+            Ok(days) => {
+                tracing::info!("Recomputed {} daily aggregates", days);
+                watermark = Some(started);
+            }
+            Err(e) => tracing::warn!("Error computing daily aggregates: {:?}", e)
+        }
+    }
+}
+
+async fn refresh(state: &AppState, watermark: Option<DateTime<Utc>>) -> Result<usize, AppError> {
+    let filter = match watermark {
+        Some(since) => doc! { "updated_at": { "$gte": BsonDateTime::from_chrono(since) } },
+        None => doc! {}
+    };
+    // a lagging secondary would hide writes from before the watermark for good
+    let options = FindOptions::builder().projection(doc! { "date": 1 }).build();
+    let mut cursor = state.timed("find", &filter, state.collection.clone_with_type::<WheightMeasurementDateEntity>().find(filter.clone(), state.bounded_primary(options))).await?;
+    let mut days = BTreeSet::new();
+    while cursor.advance().await? {
+        days.insert(cursor.deserialize_current()?.date.to_chrono().date_naive());
+    }
+
+    for day in &days {
+        recompute_day(state, *day).await?;
+    }
+    Ok(days.len())
+}
+
+// recomputes the days a write touched right away, instead of waiting for the next run; a day
+// only aggregates its own measurements, so nothing else can go stale. Failures are only logged,
+// the next run picks the new date up again through `updated_at`
+pub async fn refresh_days(state: &AppState, days: &[NaiveDate]) {
+    let days: BTreeSet<NaiveDate> = days.iter().copied().collect();
+    for day in days {
+        if let Err(e) = recompute_day(state, day).await {
+            tracing::warn!("Error recomputing the daily aggregate of {}: {:?}", day, e);
+        }
+    }
+}
+
+pub async fn recompute_day(state: &AppState, day: NaiveDate) -> Result<(), AppError> {
+    let (start, end) = (day_start(day), day_start(day.succ_opt().unwrap()));
+    let filter = doc! { "date": { "$gte": BsonDateTime::from_chrono(start), "$lt": BsonDateTime::from_chrono(end) } };
+    let options = FindOptions::builder().sort(doc! { "date": 1 }).build();
+    let mut cursor = state.timed("find", &filter, state.collection.find(filter.clone(), state.bounded_primary(options))).await?;
+    let mut weights = Vec::new();
+    while cursor.advance().await? {
+        weights.push(cursor.deserialize_current()?.wheight_kg);
+    }
+
+    let id = day.to_string();
+    let Some(&last_wheight_kg) = weights.last() else {
+        // nothing left on that day
+        let filter = doc! { "_id": &id };
+        state.timed("delete_one", &filter, state.aggregates.delete_one(filter.clone(), None)).await?;
+        return Ok(());
+    };
+    let aggregate = DailyAggregateEntity {
+        _id: id.clone(),
+        date: BsonDateTime::from_chrono(start),
+        count: weights.len() as i64,
+        avg_wheight_kg: weights.iter().sum::<f32>() / weights.len() as f32,
+        min_wheight_kg: weights.iter().copied().fold(f32::INFINITY, f32::min),
+        max_wheight_kg: weights.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+        last_wheight_kg,
+        computed_at: BsonDateTime::now()
+    };
+    let options = ReplaceOptions::builder().upsert(true).build();
+    let filter = doc! { "_id": &id };
+    state.timed("replace_one", &filter, state.aggregates.replace_one(filter.clone(), aggregate, options)).await?;
+    Ok(())
+}
@@ -0,0 +1,369 @@
+use std::collections::BTreeMap;
+
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Duration, NaiveDate, Utc, Weekday};
+use chrono_tz::Tz;
+use mongodb::{bson::{self, doc, DateTime as BsonDateTime, Document}, options::{AggregateOptions, FindOptions}};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    daily_values::{self, collapsed_wheights, Daily, DailyValue}, error::AppError, timezone::{local_day_start, Timezone},
+    trend::days_since, units::{OutputUnit, Unit}, AppState,
+};
+
+// `date` filter for an optional `[from, to]` range
+pub fn date_range_filter(from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Document {
+    let mut range = Document::new();
+    if let Some(from) = from {
+        range.insert("$gte", BsonDateTime::from_chrono(from));
+    }
+    if let Some(to) = to {
+        range.insert("$lte", BsonDateTime::from_chrono(to));
+    }
+    if range.is_empty() { doc! {} } else { doc! { "date": range } }
+}
+
+// output order of the weekday buckets
+const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+
+#[derive(Deserialize)]
+struct WeekdayGroup {
+    // `$dayOfWeek`: 1 is Sunday through 7 Saturday
+    _id: i32,
+    avg_wheight_kg: f64,
+    count: i64
+}
+
+#[derive(Serialize)]
+struct WeekdayOutput {
+    weekday: &'static str,
+    avg_wheight_kg: Option<f32>,
+    count: i64,
+    unit: Unit
+}
+
+// average weight per day of the week in `tz`, always all seven days in Mon-Sun order; in UTC
+// it's read from the daily aggregates (each day weighted by its number of measurements), whose
+// days are UTC ones, elsewhere from the measurements themselves
+pub async fn get_by_weekday(State(state): State<AppState>, Timezone(tz): Timezone, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let (collection, group) = if tz == Tz::UTC {
+        (state.aggregates.clone_with_type::<Document>(), doc! {
+            "_id": { "$dayOfWeek": "$date" },
+            "total_wheight_kg": { "$sum": { "$multiply": ["$avg_wheight_kg", "$count"] } },
+            "count": { "$sum": "$count" }
+        })
+    } else {
+        (state.collection.clone_with_type::<Document>(), doc! {
+            "_id": { "$dayOfWeek": { "date": "$date", "timezone": tz.name() } },
+            "total_wheight_kg": { "$sum": "$wheight_kg" },
+            "count": { "$sum": 1 }
+        })
+    };
+    let pipeline = vec![
+        doc! { "$group": group },
+        doc! { "$project": {
+            "avg_wheight_kg": { "$divide": ["$total_wheight_kg", "$count"] },
+            "count": 1
+        } }
+    ];
+    let mut cursor = state.timed("aggregate", &pipeline, collection.aggregate(pipeline.clone(), state.bounded(AggregateOptions::default()))).await?;
+
+    let mut buckets: Vec<WeekdayOutput> = WEEKDAYS.iter()
+        .map(|weekday| WeekdayOutput { weekday, avg_wheight_kg: None, count: 0, unit })
+        .collect();
+    while cursor.advance().await? {
+        let group: WeekdayGroup = bson::from_document(cursor.deserialize_current()?)?;
+        // Sunday (1) goes last, Monday (2) first
+        let index = ((group._id + 5) % 7) as usize;
+        buckets[index].avg_wheight_kg = Some(unit.convert_kg(group.avg_wheight_kg as f32));
+        buckets[index].count = group.count;
+    }
+
+    Ok((StatusCode::OK, Json(buckets)))
+}
+
+#[derive(Deserialize)]
+pub struct DailyQuery {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    interpolate: Option<String>
+}
+
+#[derive(Serialize)]
+struct DailyPoint {
+    date: NaiveDate,
+    wheight_kg: f32,
+    interpolated: bool,
+    unit: Unit
+}
+
+// one point per calendar day between the first and last known ones, filling gaps on
+// the straight line between their neighbours; nothing is extrapolated past the ends
+fn interpolate_daily(known: &BTreeMap<NaiveDate, f32>) -> Vec<DailyPoint> {
+    let mut points = Vec::new();
+    let mut previous: Option<(NaiveDate, f32)> = None;
+    for (&date, &wheight_kg) in known {
+        if let Some((previous_date, previous_kg)) = previous {
+            let span = (date - previous_date).num_days() as f32;
+            let mut day = previous_date.succ_opt();
+            while let Some(d) = day.filter(|d| *d < date) {
+                let t = (d - previous_date).num_days() as f32 / span;
+                points.push(DailyPoint { date: d, wheight_kg: previous_kg + t * (wheight_kg - previous_kg), interpolated: true, unit: Unit::Kg });
+                day = d.succ_opt();
+            }
+        }
+        points.push(DailyPoint { date, wheight_kg, interpolated: false, unit: Unit::Kg });
+        previous = Some((date, wheight_kg));
+    }
+    points
+}
+
+// daily weight series, each day's last measurement in the calendar of `tz`, optionally
+// gap-filled with `interpolate=linear`; in UTC it's served from the daily aggregates, so it can
+// trail new writes by up to one aggregation interval, elsewhere it's `/daily-values`' series
+pub async fn get_daily(State(state): State<AppState>, Query(query): Query<DailyQuery>, Timezone(tz): Timezone, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let interpolate = match query.interpolate.as_deref() {
+        None | Some("none") => false,
+        Some("linear") => true,
+        Some(other) => return Err(AppError::BadRequest(format!("Unknown interpolation '{}', expected linear or none", other)))
+    };
+
+    // `from` covers its whole day
+    let from = query.from.map(|from| local_day_start(from.with_timezone(&tz).date_naive(), tz));
+    let mut known = BTreeMap::new();
+    if tz == Tz::UTC {
+        let options = FindOptions::builder().sort(doc! { "date": 1 }).build();
+        let filter = date_range_filter(from, query.to);
+        let mut cursor = state.timed("find", &filter, state.aggregates.find(filter.clone(), state.bounded(options))).await?;
+        while cursor.advance().await? {
+            let aggregate = cursor.deserialize_current()?;
+            known.insert(aggregate.date.to_chrono().date_naive(), aggregate.last_wheight_kg);
+        }
+    } else {
+        let points = daily_values::daily_values(&state, from, query.to, Daily { value: DailyValue::Last, tz }).await?;
+        known.extend(points.into_iter().map(|p| (p.day, p.wheight_kg as f32)));
+    }
+
+    let points = if interpolate {
+        interpolate_daily(&known)
+    } else {
+        known.into_iter().map(|(date, wheight_kg)| DailyPoint { date, wheight_kg, interpolated: false, unit: Unit::Kg }).collect()
+    };
+    let points: Vec<DailyPoint> = points.into_iter()
+        .map(|p| DailyPoint { wheight_kg: unit.convert_kg(p.wheight_kg), unit, ..p })
+        .collect();
+    Ok((StatusCode::OK, Json(points)))
+}
+
+// longest range one calendar request may cover, a year and a leap day
+const MAX_CALENDAR_DAYS: i64 = 366;
+
+#[derive(Deserialize)]
+pub struct CalendarQuery {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    // include the day's last weight
+    #[serde(default)]
+    values: bool
+}
+
+#[derive(Serialize)]
+struct CalendarDay {
+    date: NaiveDate,
+    logged: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<Unit>
+}
+
+// every day of `[from, to]` in the calendar of `tz` (the last year by default) and whether
+// anything was logged on it, for a heatmap
+pub async fn get_streak_calendar(State(state): State<AppState>, Query(query): Query<CalendarQuery>, Timezone(tz): Timezone, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let to = query.to.unwrap_or_else(|| Utc::now().with_timezone(&tz).date_naive());
+    let from = query.from.unwrap_or(to - Duration::days(MAX_CALENDAR_DAYS - 1));
+    if from > to {
+        return Err(AppError::BadRequest("from must not be after to".to_string()));
+    }
+    if (to - from).num_days() >= MAX_CALENDAR_DAYS {
+        return Err(AppError::BadRequest(format!("the range can cover at most {} days", MAX_CALENDAR_DAYS)));
+    }
+
+    let filter = doc! { "date": {
+        "$gte": BsonDateTime::from_chrono(local_day_start(from, tz)),
+        "$lt": BsonDateTime::from_chrono(local_day_start(to.succ_opt().unwrap(), tz))
+    } };
+    let logged: BTreeMap<NaiveDate, f32> = collapsed_wheights(&state, filter, Daily { value: DailyValue::Last, tz }).await?.into_iter()
+        .map(|p| (p.day, p.wheight_kg as f32))
+        .collect();
+
+    let days: Vec<CalendarDay> = from.iter_days().take_while(|d| *d <= to)
+        .map(|date| {
+            let wheight_kg = logged.get(&date);
+            let value = wheight_kg.filter(|_| query.values).map(|w| unit.convert_kg(*w));
+            CalendarDay { date, logged: wheight_kg.is_some(), value, unit: value.map(|_| unit) }
+        })
+        .collect();
+    Ok((StatusCode::OK, Json(days)))
+}
+
+#[derive(Deserialize)]
+pub struct WeeklyQuery {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>
+}
+
+#[derive(Deserialize)]
+struct WeekGroup {
+    iso_year: i32,
+    iso_week: u32,
+    avg_wheight_kg: f64,
+    count: i64,
+    days_logged: i64,
+    first_date: BsonDateTime,
+    first_wheight_kg: f32,
+    last_date: BsonDateTime,
+    last_wheight_kg: f32
+}
+
+#[derive(Serialize)]
+struct WeekOutput {
+    // Monday of the ISO week
+    week_start: NaiveDate,
+    // all null (and the counts 0) for a week without measurements
+    avg_wheight_kg: Option<f32>,
+    count: i64,
+    days_logged: i64,
+    // last reading of the week minus its first, and that spread over the days between them;
+    // the slope is null with a single reading
+    change_wheight_kg: Option<f32>,
+    slope_kg_per_day: Option<f32>,
+    unit: Unit
+}
+
+impl WeekOutput {
+    fn empty(week_start: NaiveDate, unit: Unit) -> Self {
+        WeekOutput { week_start, avg_wheight_kg: None, count: 0, days_logged: 0, change_wheight_kg: None, slope_kg_per_day: None, unit }
+    }
+}
+
+// average weight per ISO week of `tz` with how it moved within the week, oldest first and with
+// the weeks between the first and last ones filled in
+pub async fn get_weekly(State(state): State<AppState>, Query(query): Query<WeeklyQuery>, Timezone(tz): Timezone, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let local = |operator: &str| doc! { operator: { "date": "$date", "timezone": tz.name() } };
+    let pipeline = vec![
+        doc! { "$match": date_range_filter(query.from, query.to) },
+        doc! { "$sort": { "date": 1 } },
+        doc! { "$group": {
+            "_id": { "iso_year": local("$isoWeekYear"), "iso_week": local("$isoWeek") },
+            "avg_wheight_kg": { "$avg": "$wheight_kg" },
+            "count": { "$sum": 1 },
+            "days": { "$addToSet": { "$dateToString": { "format": "%Y-%m-%d", "date": "$date", "timezone": tz.name() } } },
+            "first_date": { "$first": "$date" },
+            "first_wheight_kg": { "$first": "$wheight_kg" },
+            "last_date": { "$last": "$date" },
+            "last_wheight_kg": { "$last": "$wheight_kg" }
+        } },
+        doc! { "$project": {
+            "_id": 0,
+            "iso_year": "$_id.iso_year",
+            "iso_week": "$_id.iso_week",
+            "avg_wheight_kg": 1,
+            "count": 1,
+            "days_logged": { "$size": "$days" },
+            "first_date": 1,
+            "first_wheight_kg": 1,
+            "last_date": 1,
+            "last_wheight_kg": 1
+        } }
+    ];
+    let mut cursor = state.timed("aggregate", &pipeline, state.collection.aggregate(pipeline.clone(), state.bounded(AggregateOptions::default()))).await?;
+
+    let mut weeks = BTreeMap::new();
+    while cursor.advance().await? {
+        let group: WeekGroup = bson::from_document(cursor.deserialize_current()?)?;
+        let Some(week_start) = NaiveDate::from_isoywd_opt(group.iso_year, group.iso_week, Weekday::Mon) else {
+            continue;
+        };
+        let change = group.last_wheight_kg - group.first_wheight_kg;
+        let days = days_since(group.first_date.to_chrono(), group.last_date.to_chrono());
+        weeks.insert(week_start, WeekOutput {
+            week_start,
+            avg_wheight_kg: Some(unit.convert_kg(group.avg_wheight_kg as f32)),
+            count: group.count,
+            days_logged: group.days_logged,
+            change_wheight_kg: Some(unit.convert_kg(change)),
+            slope_kg_per_day: (days > 0_f64).then(|| unit.convert_kg((change as f64 / days) as f32)),
+            unit
+        });
+    }
+
+    let (Some(&first), Some(&last)) = (weeks.keys().next(), weeks.keys().next_back()) else {
+        return Ok((StatusCode::OK, Json(Vec::new())));
+    };
+    let buckets: Vec<WeekOutput> = first.iter_weeks().take_while(|w| *w <= last)
+        .map(|week_start| weeks.remove(&week_start).unwrap_or_else(|| WeekOutput::empty(week_start, unit)))
+        .collect();
+    Ok((StatusCode::OK, Json(buckets)))
+}
+
+#[derive(Deserialize)]
+struct ProgressGroup {
+    first_date: BsonDateTime,
+    last_date: BsonDateTime,
+    measurements_count: i64,
+    days_tracked: i64
+}
+
+#[derive(Serialize)]
+struct ProgressOutput {
+    // both null when there is nothing yet, the counts are 0 then
+    first_date: Option<DateTime<Utc>>,
+    last_date: Option<DateTime<Utc>>,
+    // calendar days of `tz` from the first measurement's to the last one's
+    span_days: i64,
+    // distinct days with at least one measurement, against `measurements_count` in total
+    days_tracked: i64,
+    measurements_count: i64,
+    // only the most recent `AGG_MAX_DOCS` measurements were counted
+    approximate: bool
+}
+
+// the journey so far: how long it spans, how many days were logged and how many measurements
+pub async fn get_progress(State(state): State<AppState>, Timezone(tz): Timezone) -> Result<impl IntoResponse, AppError> {
+    let (filter, approximate) = state.capped(doc! {}).await?;
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$group": {
+            "_id": null,
+            "first_date": { "$min": "$date" },
+            "last_date": { "$max": "$date" },
+            "measurements_count": { "$sum": 1 },
+            "days": { "$addToSet": { "$dateToString": { "format": "%Y-%m-%d", "date": "$date", "timezone": tz.name() } } }
+        } },
+        doc! { "$project": {
+            "_id": 0,
+            "first_date": 1,
+            "last_date": 1,
+            "measurements_count": 1,
+            "days_tracked": { "$size": "$days" }
+        } }
+    ];
+    let mut cursor = state.timed("aggregate", &pipeline, state.collection.aggregate(pipeline.clone(), state.bounded(AggregateOptions::default()))).await?;
+    if !cursor.advance().await? {
+        return Ok((StatusCode::OK, Json(ProgressOutput {
+            first_date: None, last_date: None, span_days: 0, days_tracked: 0, measurements_count: 0, approximate
+        })));
+    }
+    let group: ProgressGroup = bson::from_document(cursor.deserialize_current()?)?;
+    let (first, last) = (group.first_date.to_chrono(), group.last_date.to_chrono());
+    let span_days = (last.with_timezone(&tz).date_naive() - first.with_timezone(&tz).date_naive()).num_days();
+    Ok((StatusCode::OK, Json(ProgressOutput {
+        first_date: Some(first),
+        last_date: Some(last),
+        span_days,
+        days_tracked: group.days_tracked,
+        measurements_count: group.measurements_count,
+        approximate
+    })))
+}
@@ -0,0 +1,82 @@
+use axum::{extract::{Path, State}, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use mongodb::{bson::{doc, oid::ObjectId, DateTime as BsonDateTime}, options::FindOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::AppError, parse_object_id, AppState, WheightMeasurementEntity, WheightMeasurementOutput};
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOperation {
+    Create,
+    Update,
+    Delete
+}
+
+// one change to a measurement, with the document as it was before and after it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AuditEntity {
+    _id: ObjectId,
+    measurement_id: ObjectId,
+    operation: AuditOperation,
+    at: BsonDateTime,
+    before: Option<WheightMeasurementEntity>,
+    after: Option<WheightMeasurementEntity>
+}
+
+impl AuditEntity {
+    pub fn new(operation: AuditOperation, before: Option<&WheightMeasurementEntity>, after: Option<&WheightMeasurementEntity>) -> Self {
+        AuditEntity {
+            _id: ObjectId::new(),
+            measurement_id: after.or(before).map(|m| m._id).unwrap_or_default(),
+            operation,
+            at: BsonDateTime::now(),
+            before: before.cloned(),
+            after: after.cloned()
+        }
+    }
+
+    pub fn before(&self) -> Option<&WheightMeasurementEntity> {
+        self.before.as_ref()
+    }
+}
+
+impl AppState {
+    // best-effort: the change itself already happened, so a failure here is only logged
+    pub async fn audit(&self, entries: Vec<AuditEntity>) {
+        if entries.is_empty() {
+            return;
+        }
+        let count = entries.len();
+        if let Err(e) = self.measurements.record_audit(self, entries).await {
+            tracing::error!("Error writing {} audit entries, those changes are missing from the history: {}", count, e);
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AuditOutput {
+    operation: AuditOperation,
+    at: DateTime<Utc>,
+    before: Option<WheightMeasurementOutput>,
+    after: Option<WheightMeasurementOutput>
+}
+
+// every recorded change to one measurement, oldest first; still answers after a delete
+pub async fn get_history(State(state): State<AppState>, Path(id): Path<String>) -> Result<impl IntoResponse, AppError> {
+    let filter = doc! { "measurement_id": parse_object_id(&id)? };
+    let options = FindOptions::builder().sort(doc! { "at": 1 }).build();
+    let mut cursor = state.timed("find", &filter, state.audit.find(filter.clone(), state.bounded(options))).await?;
+    let mut entries = Vec::new();
+    while cursor.advance().await? {
+        let entry = cursor.deserialize_current()?;
+        entries.push(AuditOutput {
+            operation: entry.operation,
+            at: entry.at.to_chrono(),
+            before: entry.before.map(WheightMeasurementOutput::from_entity),
+            after: entry.after.map(WheightMeasurementOutput::from_entity)
+        });
+    }
+
+    Ok((StatusCode::OK, Json(entries)))
+}
@@ -0,0 +1,81 @@
+use axum::{
+    body::{self, Body},
+    http::{header, HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+
+// longest part of a body written to the log, the request itself always gets all of it
+const MAX_LOGGED_BYTES: usize = 4096;
+
+// headers carrying credentials, logged as `<redacted>`
+const REDACTED_HEADERS: [header::HeaderName; 2] = [header::AUTHORIZATION, header::PROXY_AUTHORIZATION];
+
+// only JSON is buffered: exports and anything else streamed pass through untouched
+fn is_json(headers: &HeaderMap) -> bool {
+    headers.get(header::CONTENT_TYPE).is_some_and(|v| v.as_bytes().starts_with(b"application/json"))
+        && !headers.contains_key(header::CONTENT_DISPOSITION)
+}
+
+fn headers_for_log(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers.iter()
+        .map(|(name, value)| {
+            let value = if REDACTED_HEADERS.contains(name) {
+                "<redacted>".to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+fn body_for_log(bytes: &[u8]) -> String {
+    if bytes.len() <= MAX_LOGGED_BYTES {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+    format!("{}... ({} bytes, truncated)", String::from_utf8_lossy(&bytes[..MAX_LOGGED_BYTES]), bytes.len())
+}
+
+// with `LOG_BODIES=true`, logs the headers and JSON bodies of every request and response at
+// debug level, for troubleshooting client integrations; off by default, since bodies are
+// verbose and may hold personal data
+pub async fn log(enabled: bool, req: Request<Body>, next: Next<Body>) -> Response {
+    if !enabled {
+        return next.run(req).await;
+    }
+
+    let (parts, body) = req.into_parts();
+    let req = if is_json(&parts.headers) {
+        match hyper::body::to_bytes(body).await {
+            Ok(bytes) => {
+                tracing::debug!(headers = ?headers_for_log(&parts.headers), body = %body_for_log(&bytes), "request");
+                Request::from_parts(parts, Body::from(bytes))
+            },
+            Err(e) => {
+                tracing::error!("Error buffering request for the body log: {}", e);
+                Request::from_parts(parts, Body::empty())
+            }
+        }
+    } else {
+        tracing::debug!(headers = ?headers_for_log(&parts.headers), "request, body not logged");
+        Request::from_parts(parts, body)
+    };
+
+    let response = next.run(req).await;
+    if !is_json(response.headers()) {
+        tracing::debug!(status = %response.status(), headers = ?headers_for_log(response.headers()), "response, body not logged");
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    match hyper::body::to_bytes(body).await {
+        Ok(bytes) => {
+            tracing::debug!(status = %parts.status, headers = ?headers_for_log(&parts.headers), body = %body_for_log(&bytes), "response");
+            Response::from_parts(parts, body::boxed(Body::from(bytes)))
+        },
+        Err(e) => {
+            tracing::error!("Error buffering response for the body log: {}", e);
+            Response::from_parts(parts, body::boxed(Body::empty()))
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use mongodb::{bson::{doc, oid::ObjectId, DateTime as BsonDateTime, Document}, options::{CountOptions, FindOptions}};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{AuditEntity, AuditOperation}, error::AppError, normalize_tags, validation::{Validate, ValidatedJson}, AppState,
+    WheightMeasurementEntity,
+};
+
+#[derive(Deserialize)]
+pub struct BulkTagInput {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    #[serde(default)]
+    add_tags: Vec<String>,
+    #[serde(default)]
+    remove_tags: Vec<String>
+}
+
+impl Validate for BulkTagInput {
+    fn validate(&self) -> Result<(), AppError> {
+        if self.from > self.to {
+            return Err(AppError::Validation("from must not be after to".to_string()));
+        }
+        let (add, remove) = (normalize_tags(self.add_tags.clone()), normalize_tags(self.remove_tags.clone()));
+        if add.is_empty() && remove.is_empty() {
+            return Err(AppError::Validation("add_tags or remove_tags must name at least one tag".to_string()));
+        }
+        if let Some(tag) = add.iter().find(|tag| remove.contains(tag)) {
+            return Err(AppError::Validation(format!("'{}' can't be both added and removed", tag)));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct BulkTagOutput {
+    // measurements that gained at least one tag, and those that lost at least one
+    tagged_count: u64,
+    untagged_count: u64,
+    // locked ones the change would have applied to, left as they were
+    locked_count: u64
+}
+
+// what the two passes below do to a measurement's tags: `$addToSet` appends the missing ones,
+// `$pull` drops the removed ones, and each pass that changes something bumps the version
+fn tagged(before: &WheightMeasurementEntity, add: &[String], remove: &[String], now: BsonDateTime) -> WheightMeasurementEntity {
+    let mut after = before.clone();
+    let gains = add.iter().any(|tag| !before.tags.contains(tag));
+    let loses = remove.iter().any(|tag| before.tags.contains(tag));
+    for tag in add {
+        if !after.tags.contains(tag) {
+            after.tags.push(tag.clone());
+        }
+    }
+    after.tags.retain(|tag| !remove.contains(tag));
+    after.version = before.version + gains as u32 + loses as u32;
+    after.updated_at = Some(now);
+    after
+}
+
+// adds/removes tags on every unlocked measurement dated within `[from, to]`; only documents
+// that actually change are touched, so their `version` and `updated_at` stay meaningful
+pub async fn bulk_tag(State(state): State<AppState>, ValidatedJson(payload): ValidatedJson<BulkTagInput>) -> Result<impl IntoResponse, AppError> {
+    let (add, remove) = (normalize_tags(payload.add_tags), normalize_tags(payload.remove_tags));
+    let range = doc! { "date": {
+        "$gte": BsonDateTime::from_chrono(payload.from),
+        "$lte": BsonDateTime::from_chrono(payload.to)
+    } };
+    let gains = doc! { "$nor": [{ "tags": { "$all": &add } }] };
+    let loses = doc! { "tags": { "$in": &remove } };
+
+    // the documents about to change, for their audit entries; the updates are limited to them
+    // so one created meanwhile isn't changed without a trace
+    let mut changing = range;
+    let mut reasons = Vec::new();
+    if !add.is_empty() {
+        reasons.push(gains.clone());
+    }
+    if !remove.is_empty() {
+        reasons.push(loses.clone());
+    }
+    changing.insert("$or", reasons);
+    let mut locked = changing.clone();
+    locked.insert("is_locked", true);
+    let locked_count = state.timed("count_documents", &locked, state.collection.count_documents(locked.clone(), state.bounded_primary(CountOptions::default()))).await?;
+    changing.insert("is_locked", doc! { "$ne": true });
+    let mut cursor = state.timed("find", &changing, state.collection.find(changing.clone(), state.bounded_primary(FindOptions::default()))).await?;
+    let mut before = Vec::new();
+    while cursor.advance().await? {
+        before.push(cursor.deserialize_current()?);
+    }
+    let ids: Vec<ObjectId> = before.iter().map(|m: &WheightMeasurementEntity| m._id).collect();
+
+    let now = BsonDateTime::now();
+    let touch = |mut update: Document| {
+        update.insert("$set", doc! { "updated_at": now });
+        update.insert("$inc", doc! { "version": 1 });
+        update
+    };
+
+    // `$addToSet` and `$pull` can't target the same field in one update, hence two passes
+    let mut tagged_count = 0;
+    if !add.is_empty() {
+        let mut filter = doc! { "_id": { "$in": &ids } };
+        filter.extend(gains);
+        let update = touch(doc! { "$addToSet": { "tags": { "$each": &add } } });
+        tagged_count = state.timed("update_many", &filter, state.collection.update_many(filter.clone(), update, None)).await?.modified_count;
+    }
+    let mut untagged_count = 0;
+    if !remove.is_empty() {
+        let mut filter = doc! { "_id": { "$in": &ids } };
+        filter.extend(loses);
+        let update = touch(doc! { "$pull": { "tags": { "$in": &remove } } });
+        untagged_count = state.timed("update_many", &filter, state.collection.update_many(filter.clone(), update, None)).await?.modified_count;
+    }
+
+    state.audit(before.iter()
+        .map(|m| AuditEntity::new(AuditOperation::Update, Some(m), Some(&tagged(m, &add, &remove, now))))
+        .collect()).await;
+    // the cached latest measurement may be one of them, and the facets list the tags
+    *state.latest.write().await = None;
+    *state.facets.write().await = None;
+    Ok((StatusCode::OK, Json(BulkTagOutput { tagged_count, untagged_count, locked_count })))
+}
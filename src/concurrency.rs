@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    body::{self, Body, HttpBody},
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::Semaphore;
+
+use crate::error::AppError;
+
+// at most `MAX_CONCURRENT_REQUESTS` requests are handled at once; up to `REQUEST_QUEUE_SIZE`
+// more wait for a slot, for at most `REQUEST_QUEUE_TIMEOUT_MS`, and everything beyond is shed
+#[derive(Clone)]
+pub struct ConcurrencyLimit {
+    permits: Arc<Semaphore>,
+    queued: Arc<AtomicUsize>,
+    queue_size: usize,
+    queue_timeout: Duration
+}
+
+impl ConcurrencyLimit {
+    pub fn new(max_concurrent: usize, queue_size: usize, queue_timeout: Duration) -> Self {
+        ConcurrencyLimit {
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+            queued: Arc::new(AtomicUsize::new(0)),
+            queue_size,
+            queue_timeout
+        }
+    }
+}
+
+struct QueuedGuard(Arc<AtomicUsize>);
+
+impl Drop for QueuedGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn shed(reason: &str) -> Response {
+    tracing::warn!("Shedding request, {}", reason);
+    AppError::Overloaded.into_response()
+}
+
+// the permit rides along with the body, so a streamed export holds its slot until fully sent
+pub async fn limit(limit: ConcurrencyLimit, req: Request<Body>, next: Next<Body>) -> Response {
+    let permit = match limit.permits.clone().try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(_) => {
+            if limit.queued.fetch_add(1, Ordering::SeqCst) >= limit.queue_size {
+                limit.queued.fetch_sub(1, Ordering::SeqCst);
+                return shed("the queue is full");
+            }
+            let _queued = QueuedGuard(limit.queued.clone());
+            match tokio::time::timeout(limit.queue_timeout, limit.permits.clone().acquire_owned()).await {
+                Ok(Ok(permit)) => permit,
+                // the semaphore is never closed
+                Ok(Err(_)) => return shed("the limiter is closed"),
+                Err(_) => return shed("no slot freed up in time")
+            }
+        }
+    };
+
+    let response = next.run(req).await;
+    response.map(move |body| body::boxed(body.map_data(move |data| {
+        let _ = &permit;
+        data
+    })))
+}
@@ -0,0 +1,227 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use chrono_tz::Tz;
+
+use mongodb::options::{Acknowledgment, ReadPreference, ReadPreferenceOptions, SelectionCriteria, WriteConcern};
+
+use crate::{datasets, noise::NoiseThresholds, read_env_var};
+
+// `MONGODB_READ_PREFERENCE`, which members serve reads; writes always go to the primary
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadPreferenceMode {
+    Primary,
+    SecondaryPreferred,
+    Nearest
+}
+
+impl ReadPreferenceMode {
+    // `None` for the primary, which is already the client default
+    pub fn selection_criteria(self) -> Option<SelectionCriteria> {
+        let options = ReadPreferenceOptions::default();
+        match self {
+            ReadPreferenceMode::Primary => None,
+            ReadPreferenceMode::SecondaryPreferred => Some(SelectionCriteria::ReadPreference(ReadPreference::SecondaryPreferred { options })),
+            ReadPreferenceMode::Nearest => Some(SelectionCriteria::ReadPreference(ReadPreference::Nearest { options }))
+        }
+    }
+}
+
+// `MONGODB_WRITE_CONCERN`, how many members must acknowledge a write: `majority` or a count
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteConcernMode {
+    Majority,
+    Nodes(u32)
+}
+
+impl WriteConcernMode {
+    pub fn write_concern(self) -> WriteConcern {
+        let w = match self {
+            WriteConcernMode::Majority => Acknowledgment::Majority,
+            WriteConcernMode::Nodes(n) => Acknowledgment::Nodes(n)
+        };
+        WriteConcern::builder().w(w).build()
+    }
+}
+
+impl FromStr for WriteConcernMode {
+    type Err = ();
+
+    // `0` (unacknowledged) is refused, updates read their result back from the acknowledgment
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "majority" => Ok(WriteConcernMode::Majority),
+            n => n.parse().ok().filter(|n| *n > 0).map(WriteConcernMode::Nodes).ok_or(())
+        }
+    }
+}
+
+impl fmt::Display for WriteConcernMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteConcernMode::Majority => f.write_str("majority"),
+            WriteConcernMode::Nodes(n) => write!(f, "{}", n)
+        }
+    }
+}
+
+impl FromStr for ReadPreferenceMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "primary" => Ok(ReadPreferenceMode::Primary),
+            "secondaryPreferred" => Ok(ReadPreferenceMode::SecondaryPreferred),
+            "nearest" => Ok(ReadPreferenceMode::Nearest),
+            _ => Err(())
+        }
+    }
+}
+
+impl fmt::Display for ReadPreferenceMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReadPreferenceMode::Primary => "primary",
+            ReadPreferenceMode::SecondaryPreferred => "secondaryPreferred",
+            ReadPreferenceMode::Nearest => "nearest"
+        })
+    }
+}
+
+// settings read once from the environment at startup
+#[derive(Clone, Debug)]
+pub struct Config {
+    // how long in-flight requests get to finish after a shutdown signal
+    pub shutdown_timeout: Duration,
+    // how often the daily aggregates are brought up to date
+    pub aggregate_interval: Duration,
+    // comma-separated `CORS_ALLOWED_ORIGINS`, empty allows any origin (without credentials)
+    pub cors_origins: Vec<String>,
+    // list page size when `limit` isn't given, and the most a request may ask for
+    pub default_page_size: i64,
+    pub max_page_size: i64,
+    // measurements older than this many days are purged, 0 keeps everything
+    pub retention_days: u32,
+    pub retention_interval: Duration,
+    // database operations slower than this are logged as warnings
+    pub slow_query: Duration,
+    // server-side `maxTimeMS` of every read
+    pub db_op_timeout: Duration,
+    // PEM files to serve HTTPS with, plain HTTP when neither is set
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    pub read_preference: ReadPreferenceMode,
+    pub write_concern: WriteConcernMode,
+    // requests handled at once, and how many more wait (and for how long) before being shed
+    pub max_concurrent_requests: usize,
+    pub request_queue_size: usize,
+    pub request_queue_timeout: Duration,
+    // bearer token of the admin operations, which are disabled without one
+    pub admin_token: Option<String>,
+    // `NOISE_THRESHOLD_<METRIC>`, used for the metrics the profile sets no threshold for
+    pub noise_thresholds: NoiseThresholds,
+    // IANA name of the calendar days and weeks are grouped in unless a request passes `?tz=`
+    pub default_timezone: Tz,
+    // `SEND_SERVER_HEADER`, whether responses name the service and its version in `Server`
+    pub send_server_header: bool,
+    // most measurements the statistics read, larger histories only count the most recent ones
+    pub agg_max_docs: u64,
+    // `IMC_TOLERANCE`, how far a submitted `imc` may be from the one computed from the profile
+    // height before the create response warns about it
+    pub imc_tolerance: f32,
+    // `DATASETS`, comma-separated names served under `/v1/<dataset>/...` from `ds_<dataset>_*`
+    // collections of their own; names outside `datasets::is_safe_name` are dropped with a warning
+    pub datasets: Vec<String>,
+    // `FIXED_POINT_NUMBERS`, whether JSON bodies write floats without exponents, see
+    // `number_format`
+    pub fixed_point_numbers: bool,
+    // `DEDUP_WINDOW_MINUTES` (at most a year), a create this close in time to an existing
+    // measurement is rejected as a duplicate; 0 allows any number of measurements at any time
+    pub dedup_window_minutes: i64,
+    // `LOG_BODIES`, debug logging of request and response bodies, see `body_log`
+    pub log_bodies: bool
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let max_page_size = parse_env("MAX_PAGE_SIZE", 200_i64).max(1);
+        Config {
+            shutdown_timeout: Duration::from_millis(parse_env("SHUTDOWN_TIMEOUT_MS", 30_000)),
+            aggregate_interval: Duration::from_secs(parse_env("AGGREGATE_INTERVAL_SECS", 300)),
+            cors_origins: read_env_var("CORS_ALLOWED_ORIGINS", "")
+                .split(',')
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect(),
+            default_page_size: parse_env("DEFAULT_PAGE_SIZE", 50_i64).clamp(1, max_page_size),
+            max_page_size,
+            retention_days: parse_env("RETENTION_DAYS", 0),
+            retention_interval: Duration::from_secs(parse_env("RETENTION_INTERVAL_SECS", 3600)),
+            slow_query: Duration::from_millis(parse_env("SLOW_QUERY_MS", 500)),
+            db_op_timeout: Duration::from_millis(parse_env("DB_OP_TIMEOUT_MS", 5_000)),
+            tls_cert_path: optional_env("TLS_CERT_PATH"),
+            tls_key_path: optional_env("TLS_KEY_PATH"),
+            read_preference: parse_env("MONGODB_READ_PREFERENCE", ReadPreferenceMode::Primary),
+            write_concern: parse_env("MONGODB_WRITE_CONCERN", WriteConcernMode::Majority),
+            max_concurrent_requests: parse_env("MAX_CONCURRENT_REQUESTS", 128_usize).max(1),
+            request_queue_size: parse_env("REQUEST_QUEUE_SIZE", 64),
+            request_queue_timeout: Duration::from_millis(parse_env("REQUEST_QUEUE_TIMEOUT_MS", 1_000)),
+            admin_token: optional_env("ADMIN_TOKEN"),
+            noise_thresholds: NoiseThresholds {
+                wheight_kg: optional_threshold("NOISE_THRESHOLD_WHEIGHT_KG"),
+                fat_percentage: optional_threshold("NOISE_THRESHOLD_FAT_PERCENTAGE"),
+                muscle_kg: optional_threshold("NOISE_THRESHOLD_MUSCLE_KG"),
+                bone_kg: optional_threshold("NOISE_THRESHOLD_BONE_KG"),
+                fat_kg: optional_threshold("NOISE_THRESHOLD_FAT_KG"),
+                muscle_percentage: optional_threshold("NOISE_THRESHOLD_MUSCLE_PERCENTAGE")
+            },
+            default_timezone: parse_env("DEFAULT_TIMEZONE", Tz::UTC),
+            send_server_header: parse_env("SEND_SERVER_HEADER", true),
+            agg_max_docs: parse_env("AGG_MAX_DOCS", 100_000_u64).max(1),
+            imc_tolerance: parse_env("IMC_TOLERANCE", 0.5_f32).max(0_f32),
+            datasets: read_env_var("DATASETS", "")
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .filter(|name| {
+                    let safe = datasets::is_safe_name(name);
+                    if !safe {
+                        tracing::warn!("Ignoring dataset {}, names are 1-32 lowercase letters, digits, '-' or '_' and not weight, profile or system*", name);
+                    }
+                    safe
+                })
+                .collect(),
+            fixed_point_numbers: parse_env("FIXED_POINT_NUMBERS", true),
+            dedup_window_minutes: parse_env("DEDUP_WINDOW_MINUTES", 0_i64).clamp(0, 365 * 24 * 60),
+            log_bodies: parse_env("LOG_BODIES", false)
+        }
+    }
+}
+
+fn optional_env(name: &str) -> Option<String> {
+    Some(read_env_var(name, "")).filter(|v| !v.is_empty())
+}
+
+// unset when missing, or when malformed or negative (with a warning)
+fn optional_threshold(name: &str) -> Option<f32> {
+    let value = optional_env(name)?;
+    match value.parse::<f32>() {
+        Ok(t) if t.is_finite() && t >= 0_f32 => Some(t),
+        _ => {
+            tracing::warn!("Invalid {}={}, using no threshold", name, value);
+            None
+        }
+    }
+}
+
+// falls back to `default` (with a warning) when the variable is missing or malformed
+fn parse_env<T: FromStr + ToString>(name: &str, default: T) -> T {
+    let value = read_env_var(name, &default.to_string());
+    match value.parse() {
+        Ok(v) => v,
+        Err(_) => {
+            tracing::warn!("Invalid {}={}, using {}", name, value, default.to_string());
+            default
+        }
+    }
+}
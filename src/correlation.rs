@@ -0,0 +1,134 @@
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use mongodb::{bson::doc, options::FindOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::{derive_fat_kg, derive_muscle_percentage, error::AppError, or_derived, AppState, WheightMeasurementEntity};
+
+// fewer pairs than this and the coefficient says more about chance than about the metrics
+const MIN_SAMPLE_SIZE: usize = 10;
+
+// metrics that can be correlated or binned; units don't matter to the coefficient, which is scale-free
+const METRICS: [&str; 12] = [
+    "wheight_kg", "imc", "fat_percentage", "water_percentage", "protein_percentage", "metabolism_kcal",
+    "visceral_fat_index", "muscle_kg", "bone_kg", "metabolic_age", "fat_kg", "muscle_percentage",
+];
+
+pub fn metric_value(entity: &WheightMeasurementEntity, metric: &str) -> f64 {
+    (match metric {
+        "wheight_kg" => entity.wheight_kg,
+        "imc" => entity.imc,
+        "fat_percentage" => entity.fat_percentage,
+        "water_percentage" => entity.water_percentage,
+        "protein_percentage" => entity.protein_percentage,
+        "metabolism_kcal" => entity.metabolism_kcal,
+        "visceral_fat_index" => entity.visceral_fat_index,
+        "muscle_kg" => entity.muscle_kg,
+        "bone_kg" => entity.bone_kg,
+        "metabolic_age" => entity.metabolic_age as f32,
+        "fat_kg" => or_derived(entity.fat_kg, || derive_fat_kg(entity.wheight_kg, entity.fat_percentage)),
+        "muscle_percentage" => or_derived(entity.muscle_percentage, || derive_muscle_percentage(entity.muscle_kg, entity.wheight_kg)),
+        _ => unreachable!("metric names are validated against METRICS")
+    }) as f64
+}
+
+pub fn parse_metric(name: &str) -> Result<&'static str, AppError> {
+    METRICS.iter().find(|known| **known == name).copied()
+        .ok_or_else(|| AppError::BadRequest(format!("Unknown metric '{}', expected one of {}", name, METRICS.join(", "))))
+}
+
+// Pearson's r, `None` when either side doesn't vary at all
+fn pearson(pairs: &[(f64, f64)]) -> Option<f64> {
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut covariance, mut variance_x, mut variance_y) = (0_f64, 0_f64, 0_f64);
+    for (x, y) in pairs {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance_x += (x - mean_x).powi(2);
+        variance_y += (y - mean_y).powi(2);
+    }
+    (variance_x > 0_f64 && variance_y > 0_f64).then(|| covariance / (variance_x * variance_y).sqrt())
+}
+
+#[derive(Deserialize)]
+pub struct CorrelationQuery {
+    x: String,
+    y: String,
+    // correlate the changes between consecutive measurements instead of the values themselves
+    #[serde(default)]
+    changes: bool
+}
+
+#[derive(Serialize)]
+struct CorrelationOutput {
+    x: &'static str,
+    y: &'static str,
+    changes: bool,
+    // between -1 and 1
+    coefficient: f64,
+    sample_size: usize,
+    // measurements missing one of the metrics (reported as 0 by scales without it)
+    skipped: usize,
+    // only the most recent `AGG_MAX_DOCS` measurements were read
+    approximate: bool
+}
+
+// Pearson correlation between two metrics over every measurement (or, with `changes=true`,
+// over their day-to-day changes)
+pub async fn get_correlation(State(state): State<AppState>, Query(query): Query<CorrelationQuery>) -> Result<impl IntoResponse, AppError> {
+    let (x, y) = (parse_metric(&query.x)?, parse_metric(&query.y)?);
+
+    let (filter, approximate) = state.capped(doc! {}).await?;
+    let options = FindOptions::builder().sort(doc! { "date": 1 }).build();
+    let mut cursor = state.timed("find", &filter, state.collection.find(filter.clone(), state.bounded(options))).await?;
+    let (mut values, mut skipped) = (Vec::new(), 0);
+    while cursor.advance().await? {
+        let m = cursor.deserialize_current()?;
+        let (vx, vy) = (metric_value(&m, x), metric_value(&m, y));
+        if vx == 0_f64 || vy == 0_f64 {
+            skipped += 1;
+            continue;
+        }
+        values.push((vx, vy));
+    }
+    let pairs: Vec<(f64, f64)> = if query.changes {
+        values.windows(2).map(|w| (w[1].0 - w[0].0, w[1].1 - w[0].1)).collect()
+    } else {
+        values
+    };
+
+    if pairs.len() < MIN_SAMPLE_SIZE {
+        return Err(AppError::Validation(format!("{} pairs of {} and {}, at least {} are needed", pairs.len(), x, y, MIN_SAMPLE_SIZE)));
+    }
+    let Some(coefficient) = pearson(&pairs) else {
+        return Err(AppError::Validation(format!("{} or {} doesn't vary, there is nothing to correlate", x, y)));
+    };
+    Ok((StatusCode::OK, Json(CorrelationOutput { x, y, changes: query.changes, coefficient, sample_size: pairs.len(), skipped, approximate })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pearson_of_lines_is_one_either_way() {
+        let rising = [(1.0, 2.0), (2.0, 4.0), (3.0, 6.0)];
+        assert!((pearson(&rising).unwrap() - 1.0).abs() < 1e-12);
+        let falling = [(1.0, 6.0), (2.0, 4.0), (3.0, 2.0)];
+        assert!((pearson(&falling).unwrap() + 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn pearson_of_unrelated_values_is_zero() {
+        let pairs = [(1.0, 1.0), (2.0, 3.0), (3.0, 1.0)];
+        assert!(pearson(&pairs).unwrap().abs() < 1e-12);
+    }
+
+    #[test]
+    fn pearson_needs_variation_on_both_sides() {
+        assert!(pearson(&[]).is_none());
+        assert!(pearson(&[(1.0, 80.0)]).is_none());
+        assert!(pearson(&[(1.0, 80.0), (2.0, 80.0), (3.0, 80.0)]).is_none());
+        assert!(pearson(&[(5.0, 80.0), (5.0, 81.0)]).is_none());
+    }
+}
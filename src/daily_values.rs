@@ -0,0 +1,164 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query, State},
+    http::{request::Parts, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use mongodb::{bson::{doc, DateTime as BsonDateTime, Document}, options::AggregateOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::{analytics::date_range_filter, error::AppError, timezone::Timezone, units::{OutputUnit, Unit}, AppState};
+
+// which of a day's measurements stands for it when several were taken
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DailyValue {
+    First,
+    Last,
+    Min,
+    Avg
+}
+
+impl DailyValue {
+    fn parse(name: &str) -> Result<Self, AppError> {
+        match name {
+            "first" => Ok(DailyValue::First),
+            "last" => Ok(DailyValue::Last),
+            "min" => Ok(DailyValue::Min),
+            "avg" => Ok(DailyValue::Avg),
+            other => Err(AppError::BadRequest(format!("Unknown daily value '{}', expected first, last, min or avg", other)))
+        }
+    }
+
+    fn operator(self) -> &'static str {
+        match self {
+            DailyValue::First => "$first",
+            DailyValue::Last => "$last",
+            DailyValue::Min => "$min",
+            DailyValue::Avg => "$avg"
+        }
+    }
+}
+
+// one point per day in the calendar of `tz`
+#[derive(Clone, Copy)]
+pub struct Daily {
+    pub value: DailyValue,
+    pub tz: Tz
+}
+
+#[derive(Deserialize)]
+struct DailyQuery {
+    daily: Option<String>
+}
+
+// `?daily=first|last|min|avg`, with the `?tz=` its days are counted in
+pub struct Collapse {
+    value: Option<DailyValue>,
+    tz: Tz
+}
+
+impl Collapse {
+    // for endpoints that only collapse when asked to
+    pub fn requested(&self) -> Option<Daily> {
+        self.value.map(|value| Daily { value, tz: self.tz })
+    }
+
+    // for those that always collapse, the day's last measurement by default
+    pub fn or_last(&self) -> Daily {
+        Daily { value: self.value.unwrap_or(DailyValue::Last), tz: self.tz }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for Collapse {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<DailyQuery>::from_request_parts(parts, state).await
+            .map_err(|e| AppError::BadRequest(e.to_string()))?;
+        let value = query.daily.as_deref().map(DailyValue::parse).transpose()?;
+        let Timezone(tz) = Timezone::from_request_parts(parts, state).await?;
+        Ok(Collapse { value, tz })
+    }
+}
+
+#[derive(Deserialize)]
+struct DayGroup {
+    // the day, as `%Y-%m-%d`
+    _id: String,
+    first_date: BsonDateTime,
+    last_date: BsonDateTime,
+    wheight_kg: f64,
+    count: i64
+}
+
+pub struct DayPoint {
+    pub day: NaiveDate,
+    // of the measurement the value comes from, the day's last one for `min` and `avg`
+    pub date: DateTime<Utc>,
+    pub wheight_kg: f64,
+    pub count: i64
+}
+
+// the measurements matching `filter` collapsed to one weight per day, oldest first
+pub async fn collapsed_wheights(state: &AppState, filter: Document, daily: Daily) -> mongodb::error::Result<Vec<DayPoint>> {
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$sort": { "date": 1 } },
+        doc! { "$group": {
+            "_id": { "$dateToString": { "format": "%Y-%m-%d", "date": "$date", "timezone": daily.tz.name() } },
+            "first_date": { "$first": "$date" },
+            "last_date": { "$last": "$date" },
+            "wheight_kg": { daily.value.operator(): "$wheight_kg" },
+            "count": { "$sum": 1 }
+        } },
+        doc! { "$sort": { "_id": 1 } }
+    ];
+    let cursor = state.timed("aggregate", &pipeline, state.collection.aggregate(pipeline.clone(), state.bounded(AggregateOptions::default()))).await?;
+    let mut cursor = cursor.with_type::<DayGroup>();
+    let mut points = Vec::new();
+    while cursor.advance().await? {
+        let group = cursor.deserialize_current()?;
+        let Ok(day) = NaiveDate::parse_from_str(&group._id, "%Y-%m-%d") else {
+            continue;
+        };
+        let date = if daily.value == DailyValue::First { group.first_date } else { group.last_date };
+        points.push(DayPoint { day, date: date.to_chrono(), wheight_kg: group.wheight_kg, count: group.count });
+    }
+    Ok(points)
+}
+
+#[derive(Deserialize)]
+pub struct RangeQuery {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>
+}
+
+#[derive(Serialize)]
+struct DailyValueOutput {
+    day: NaiveDate,
+    date: DateTime<Utc>,
+    wheight_kg: f32,
+    // measurements taken that day
+    count: i64,
+    unit: Unit
+}
+
+// the collapsed weights of the measurements within `[from, to]`, what `/daily-values` serves
+// and `/daily` outside UTC
+pub async fn daily_values(state: &AppState, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>, daily: Daily) -> mongodb::error::Result<Vec<DayPoint>> {
+    collapsed_wheights(state, date_range_filter(from, to), daily).await
+}
+
+// one weight per day for charts of users who weigh in several times a day, picked with
+// `?daily=` (the day's last by default); computed from the measurements, so unlike `/daily` in
+// UTC, which reads the daily aggregates, it doesn't trail new writes
+pub async fn get_daily_values(State(state): State<AppState>, Query(query): Query<RangeQuery>, collapse: Collapse, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let points: Vec<DailyValueOutput> = daily_values(&state, query.from, query.to, collapse.or_last()).await?.into_iter()
+        .map(|p| DailyValueOutput { day: p.day, date: p.date, wheight_kg: unit.convert_kg(p.wheight_kg as f32), count: p.count, unit })
+        .collect();
+    Ok((StatusCode::OK, Json(points)))
+}
@@ -0,0 +1,111 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use axum::{
+    body::Body,
+    http::{uri::PathAndQuery, Request, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Router,
+};
+use hyper::service::Service;
+use mongodb::options::CollectionOptions;
+use tokio::sync::RwLock;
+
+use crate::{aggregates, error::AppError, indexes, retention, routes, AppState};
+
+// first segments of the v1 routes themselves, which can't double as dataset names; a dataset
+// path is `/v1/<dataset>/` followed by one of them
+const RESERVED: [&str; 2] = ["weight", "profile"];
+
+// lowercase so a name maps to exactly one set of collections, and short enough to leave room
+// for the affixes of its collections
+pub fn is_safe_name(name: &str) -> bool {
+    (1..=32).contains(&name.len())
+        && name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+        && !RESERVED.contains(&name)
+        && !name.starts_with("system")
+}
+
+// `ds_<name>_<kind>`: the prefix keeps them apart from the default dataset's and the internal
+// collections, and no kind ends with another, so two datasets never share one
+fn collection_name(name: &str, kind: &str) -> String {
+    format!("ds_{}_{}", name, kind)
+}
+
+impl AppState {
+    // the same service over the collections of `name`, with caches of its own; the health
+    // check sentinel collection is shared
+    fn for_dataset(&self, name: &str) -> AppState {
+        let database = self.collection.client().database(&self.collection.namespace().db);
+        let options = CollectionOptions::builder().write_concern(self.config.write_concern.write_concern()).build();
+        let collection = database.collection_with_options(&collection_name(name, "measurements"), options.clone());
+        AppState {
+            config: self.config.clone(),
+            measurements: Arc::new(collection.clone()),
+            collection,
+            profiles: database.collection_with_options(&collection_name(name, "profile"), options.clone()),
+            aggregates: database.collection_with_options(&collection_name(name, "daily_aggregates"), options.clone()),
+            audit: database.collection_with_options(&collection_name(name, "audit"), options),
+            healthcheck: self.healthcheck.clone(),
+            latest: Arc::new(RwLock::new(None)),
+            profile: Arc::new(RwLock::new(None)),
+            facets: Arc::new(RwLock::new(None))
+        }
+    }
+}
+
+// the full v1 router of every configured dataset, each bound to its own state; a `Router`
+// isn't `Sync`, so each is cloned out from behind a lock for the request it serves
+#[derive(Clone, Default)]
+pub struct Datasets(Arc<HashMap<String, Mutex<Router>>>);
+
+impl Datasets {
+    // also starts each dataset's index creation and background jobs, like the default one's
+    pub fn start(state: &AppState) -> Datasets {
+        let config = &state.config;
+        let routers = config.datasets.iter().map(|name| {
+            let dataset = state.for_dataset(name);
+            tokio::spawn(indexes::ensure_indexes(dataset.collection.clone(), dataset.audit.clone()));
+            tokio::spawn(aggregates::run(dataset.clone(), config.aggregate_interval));
+            if config.retention_days > 0 {
+                tokio::spawn(retention::run(dataset.clone(), config.retention_days, config.retention_interval));
+            }
+            tracing::info!("Serving dataset {} under /v1/{}/", name, name);
+            (name.clone(), Mutex::new(routes::router().with_state(dataset)))
+        }).collect();
+        Datasets(Arc::new(routers))
+    }
+}
+
+// `/v1/<dataset>/<rest>` is answered by the dataset's router as `/v1/<rest>`; a path of that
+// shape names an unknown dataset when `<dataset>` isn't configured, anything else goes on to
+// the routes, whose fallback answers unknown ones
+pub async fn dispatch(datasets: Datasets, mut req: Request<Body>, next: Next<Body>) -> Response {
+    let Some(rest) = req.uri().path().strip_prefix("/v1/") else {
+        return next.run(req).await;
+    };
+    let (name, rest) = rest.split_once('/').unwrap_or((rest, ""));
+    let route = rest.split('/').next().unwrap_or("");
+    if RESERVED.contains(&name) || !RESERVED.contains(&route) {
+        return next.run(req).await;
+    }
+    let Some(mut router) = datasets.0.get(name).map(|router| router.lock().unwrap().clone()) else {
+        return AppError::BadRequest(format!("Unknown dataset '{}'", name)).into_response();
+    };
+
+    let path = match req.uri().query() {
+        Some(query) => format!("/v1/{}?{}", rest, query),
+        None => format!("/v1/{}", rest)
+    };
+    let mut parts = req.uri().clone().into_parts();
+    parts.path_and_query = path.parse::<PathAndQuery>().ok();
+    match Uri::from_parts(parts) {
+        Ok(uri) => *req.uri_mut() = uri,
+        Err(_) => return AppError::BadRequest("Invalid path".to_string()).into_response()
+    }
+    match router.call(req).await {
+        Ok(response) => response,
+        Err(infallible) => match infallible {}
+    }
+}
+
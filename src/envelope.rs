@@ -0,0 +1,71 @@
+use axum::{
+    body::{self, Body},
+    http::{header, Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+// pagination a list handler attaches to its response, surfaced in the envelope `meta`
+#[derive(Serialize, Clone, Copy)]
+pub struct Pagination {
+    pub page: u64,
+    pub limit: i64,
+    pub total: u64
+}
+
+#[derive(Serialize)]
+struct Meta {
+    request_id: Option<String>,
+    server_time: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pagination: Option<Pagination>
+}
+
+#[derive(Serialize)]
+struct ApiResponse {
+    data: Value,
+    meta: Meta
+}
+
+// wraps successful JSON reads in `{ data, meta }` when the client opts in with `?envelope=true`,
+// everything else (writes, errors, streamed exports) passes through untouched
+pub async fn wrap(req: Request<Body>, next: Next<Body>) -> Response {
+    let wanted = req.method() == Method::GET
+        && req.uri().query().is_some_and(|q| q.split('&').any(|pair| pair == "envelope=true"));
+    let request_id = req.headers().get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let response = next.run(req).await;
+    let is_json = response.headers().get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"))
+        // downloads are streamed, buffering them here would defeat that
+        && !response.headers().contains_key(header::CONTENT_DISPOSITION);
+    if !wanted || !response.status().is_success() || !is_json {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let pagination = parts.extensions.get::<Pagination>().copied();
+    let data = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        Err(e) => {
+            tracing::error!("Error buffering response for the envelope: {}", e);
+            return Response::from_parts(parts, body::boxed(Body::empty()));
+        }
+    };
+
+    let enveloped = ApiResponse { data, meta: Meta { request_id, server_time: Utc::now(), pagination } };
+    let mut response = Json(enveloped).into_response();
+    *response.status_mut() = parts.status;
+    for (name, value) in parts.headers.iter() {
+        if name != header::CONTENT_LENGTH && name != header::CONTENT_TYPE {
+            response.headers_mut().insert(name.clone(), value.clone());
+        }
+    }
+    response
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use mongodb::error::ErrorKind;
+use serde::Serialize;
+
+// server error code of an operation aborted by its `maxTimeMS`
+const MAX_TIME_MS_EXPIRED: i32 = 50;
+
+// machine-readable error codes, these strings are part of the API contract
+// and must not change even if the messages do
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    InvalidId,
+    NotFound,
+    ValidationFailed,
+    Conflict,
+    Locked,
+    Unauthorized,
+    Forbidden,
+    NotAcceptable,
+    DatabaseUnavailable,
+    DatabaseTimeout,
+    Overloaded,
+    Internal
+}
+
+#[derive(Serialize)]
+pub struct ErrorResponse {
+    pub code: ErrorCode,
+    pub message: String,
+    // field name to what is wrong with it, for validation failures naming fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<HashMap<String, String>>
+}
+
+#[derive(Debug)]
+pub enum AppError {
+    BadRequest(String),
+    InvalidId(String),
+    NotFound,
+    // a path no route matches, with the method and path asked for
+    UnknownRoute(String),
+    Validation(String),
+    // every invalid field of a body at once, so a form can flag them all
+    InvalidFields(HashMap<String, String>),
+    Conflict(String),
+    Locked(String),
+    // credentials missing or wrong, answered with a `WWW-Authenticate: Bearer` challenge
+    Unauthorized(String),
+    Forbidden(String),
+    NotAcceptable(String),
+    Database(mongodb::error::Error),
+    Overloaded,
+    Internal(String)
+}
+
+impl AppError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            AppError::BadRequest(_) => ErrorCode::BadRequest,
+            AppError::InvalidId(_) => ErrorCode::InvalidId,
+            AppError::NotFound | AppError::UnknownRoute(_) => ErrorCode::NotFound,
+            AppError::Validation(_) | AppError::InvalidFields(_) => ErrorCode::ValidationFailed,
+            AppError::Conflict(_) => ErrorCode::Conflict,
+            AppError::Locked(_) => ErrorCode::Locked,
+            AppError::Unauthorized(_) => ErrorCode::Unauthorized,
+            AppError::Forbidden(_) => ErrorCode::Forbidden,
+            AppError::NotAcceptable(_) => ErrorCode::NotAcceptable,
+            AppError::Database(e) => match &*e.kind {
+                ErrorKind::ServerSelection { .. } | ErrorKind::Io(_) | ErrorKind::ConnectionPoolCleared { .. } => ErrorCode::DatabaseUnavailable,
+                ErrorKind::Command(c) if c.code == MAX_TIME_MS_EXPIRED => ErrorCode::DatabaseTimeout,
+                _ => ErrorCode::Internal
+            },
+            AppError::Overloaded => ErrorCode::Overloaded,
+            AppError::Internal(_) => ErrorCode::Internal
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self.code() {
+            ErrorCode::BadRequest | ErrorCode::InvalidId => StatusCode::BAD_REQUEST,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::ValidationFailed => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::Locked => StatusCode::LOCKED,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotAcceptable => StatusCode::NOT_ACCEPTABLE,
+            ErrorCode::DatabaseUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::DatabaseTimeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::Overloaded => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            AppError::InvalidId(id) => format!("Invalid id: {}", id),
+            AppError::NotFound => "Not Found".to_string(),
+            AppError::UnknownRoute(route) => format!("Route not found: {}", route),
+            AppError::InvalidFields(errors) => {
+                let mut fields: Vec<&str> = errors.keys().map(String::as_str).collect();
+                fields.sort_unstable();
+                match fields.as_slice() {
+                    [field] => errors[*field].clone(),
+                    _ => format!("{} fields are invalid: {}", fields.len(), fields.join(", "))
+                }
+            },
+            AppError::BadRequest(m) | AppError::Validation(m) | AppError::Conflict(m) | AppError::Locked(m) | AppError::Unauthorized(m) | AppError::Forbidden(m) | AppError::NotAcceptable(m) => m.clone(),
+            // don't leak driver internals to clients, they are logged instead
+            AppError::Database(_) => match self.code() {
+                ErrorCode::DatabaseUnavailable => "Database unavailable".to_string(),
+                ErrorCode::DatabaseTimeout => "Database operation timed out".to_string(),
+                _ => "Internal server error".to_string()
+            },
+            AppError::Overloaded => "Server is busy, retry shortly".to_string(),
+            AppError::Internal(_) => "Internal server error".to_string()
+        }
+    }
+}
+
+impl From<mongodb::error::Error> for AppError {
+    fn from(e: mongodb::error::Error) -> Self {
+        AppError::Database(e)
+    }
+}
+
+impl From<mongodb::bson::ser::Error> for AppError {
+    fn from(e: mongodb::bson::ser::Error) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<mongodb::bson::de::Error> for AppError {
+    fn from(e: mongodb::bson::de::Error) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        match &self {
+            AppError::Database(e) => tracing::error!("Database error: {}", e),
+            AppError::Internal(e) => tracing::error!("Internal error: {}", e),
+            _ => {}
+        }
+        let errors = match &self {
+            AppError::InvalidFields(errors) => Some(errors.clone()),
+            _ => None
+        };
+        let body = ErrorResponse { code: self.code(), message: self.message(), errors };
+        let mut response = (self.status(), Json(body)).into_response();
+        match self {
+            AppError::Overloaded => { response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from_static("1")); },
+            AppError::Unauthorized(_) => { response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer")); },
+            _ => {}
+        }
+        response
+    }
+}
@@ -0,0 +1,203 @@
+use std::error::Error;
+use std::future::Future;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::{Bytes, StreamBody},
+    extract::State,
+    http::header,
+    response::IntoResponse,
+};
+use mongodb::{bson::doc, options::FindOptions, Cursor};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{AppState, WheightMeasurementEntity, WheightMeasurementOutput};
+
+type Chunk = Result<Bytes, io::Error>;
+type ExportResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+const CSV_HEADER: &str = "id,date,wheight_kg,imc,fat_percentage,water_percentage,protein_percentage,\
+metabolism_kcal,visceral_fat_index,muscle_kg,bone_kg,metabolic_age,fat_kg,muscle_percentage,source,notes,tags\n";
+
+// `Write` sink the zip writer fills, drained into the response body as it grows
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// every export file is written oldest first
+async fn find_all(state: &AppState) -> mongodb::error::Result<Cursor<WheightMeasurementEntity>> {
+    let options = FindOptions::builder().sort(doc! { "date": 1 }).build();
+    state.timed("find", "all", state.collection.find(None, state.bounded(options))).await
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_row(m: &WheightMeasurementOutput) -> String {
+    format!(
+        "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+        m.id, m.date.to_rfc3339(), m.wheight_kg, m.imc, m.fat_percentage, m.water_percentage,
+        m.protein_percentage, m.metabolism_kcal, m.visceral_fat_index, m.muscle_kg, m.bone_kg,
+        m.metabolic_age, m.fat_kg, m.muscle_percentage, csv_field(m.source.as_deref().unwrap_or("")),
+        csv_field(m.notes.as_deref().unwrap_or("")), csv_field(&m.tags.join(";"))
+    )
+}
+
+// hands whatever the zip writer produced so far to the response body
+async fn send(buffer: &SharedBuffer, tx: &mpsc::Sender<Chunk>) -> ExportResult {
+    let chunk = buffer.take();
+    if !chunk.is_empty() {
+        tx.send(Ok(Bytes::from(chunk))).await?;
+    }
+    Ok(())
+}
+
+async fn write_zip(state: AppState, tx: mpsc::Sender<Chunk>) -> ExportResult {
+    let (state, tx) = (&state, &tx);
+    let buffer = SharedBuffer::default();
+    let mut zip = ZipWriter::new_stream(buffer.clone());
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file("measurements.json", options)?;
+    zip.write_all(b"[")?;
+    let mut cursor = find_all(state).await?;
+    let mut first = true;
+    while cursor.advance().await? {
+        if !first {
+            zip.write_all(b",")?;
+        }
+        first = false;
+        serde_json::to_writer(&mut zip, &WheightMeasurementOutput::from_entity(cursor.deserialize_current()?))?;
+        send(&buffer, tx).await?;
+    }
+    zip.write_all(b"]")?;
+
+    zip.start_file("measurements.csv", options)?;
+    zip.write_all(CSV_HEADER.as_bytes())?;
+    let mut cursor = find_all(state).await?;
+    while cursor.advance().await? {
+        zip.write_all(csv_row(&WheightMeasurementOutput::from_entity(cursor.deserialize_current()?)).as_bytes())?;
+        send(&buffer, tx).await?;
+    }
+
+    // the height, goal and preferences the measurements are read against
+    let profile = state.profile().await.map_err(|e| format!("Error reading the profile: {:?}", e))?;
+    zip.start_file("profile.json", options)?;
+    serde_json::to_writer(&mut zip, &profile)?;
+
+    zip.finish()?;
+    send(&buffer, tx).await
+}
+
+// rows are batched into chunks of about this size rather than sent one by one
+const FLUSH_BYTES: usize = 16 * 1024;
+
+async fn write_csv(state: AppState, tx: mpsc::Sender<Chunk>) -> ExportResult {
+    let mut chunk = String::from(CSV_HEADER);
+    let mut cursor = find_all(&state).await?;
+    while cursor.advance().await? {
+        chunk.push_str(&csv_row(&WheightMeasurementOutput::from_entity(cursor.deserialize_current()?)));
+        if chunk.len() >= FLUSH_BYTES {
+            tx.send(Ok(Bytes::from(std::mem::take(&mut chunk)))).await?;
+        }
+    }
+    if !chunk.is_empty() {
+        tx.send(Ok(Bytes::from(chunk))).await?;
+    }
+    Ok(())
+}
+
+// same documents as the zip's `measurements.json`, batched like the CSV
+async fn write_json(state: AppState, tx: mpsc::Sender<Chunk>) -> ExportResult {
+    let mut chunk = b"[".to_vec();
+    let mut cursor = find_all(&state).await?;
+    let mut first = true;
+    while cursor.advance().await? {
+        if !first {
+            chunk.push(b',');
+        }
+        first = false;
+        serde_json::to_writer(&mut chunk, &WheightMeasurementOutput::from_entity(cursor.deserialize_current()?))?;
+        if chunk.len() >= FLUSH_BYTES {
+            tx.send(Ok(Bytes::from(std::mem::take(&mut chunk)))).await?;
+        }
+    }
+    chunk.push(b']');
+    tx.send(Ok(Bytes::from(chunk))).await?;
+    Ok(())
+}
+
+// runs `write` in the background, its chunks becoming the response body as they come
+fn stream_export<F, Fut>(state: AppState, name: &'static str, write: F) -> StreamBody<ReceiverStream<Chunk>>
+where
+    F: FnOnce(AppState, mpsc::Sender<Chunk>) -> Fut,
+    Fut: Future<Output = ExportResult> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel::<Chunk>(16);
+    let writing = write(state, tx.clone());
+    tokio::spawn(async move {
+        if let Err(e) = writing.await {
+            // the headers are already out, all we can do is cut the download short
+            tracing::error!("Error writing {} export: {}", name, e);
+            let _ = tx.send(Err(io::Error::other(e.to_string()))).await;
+        }
+    });
+    StreamBody::new(ReceiverStream::new(rx))
+}
+
+// full history and the profile as a zip bundle, streamed so memory stays flat for long histories
+pub async fn export_zip(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "application/zip"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"measurements.zip\"")
+        ],
+        stream_export(state, "zip", write_zip)
+    )
+}
+
+// full history as CSV, straight from the cursor
+pub async fn export_csv(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"measurements.csv\"")
+        ],
+        stream_export(state, "csv", write_csv)
+    )
+}
+
+// full history as one JSON array, without pagination or projection
+pub async fn export_json(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"measurements.json\"")
+        ],
+        stream_export(state, "json", write_json)
+    )
+}
@@ -0,0 +1,54 @@
+use std::time::{Duration, Instant};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use mongodb::{bson::Bson, options::DistinctOptions};
+use serde::Serialize;
+
+use crate::{error::AppError, find_date, AppState};
+
+// sources and tags change rarely, a little staleness is fine for filter dropdowns
+const FACETS_CACHE_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Clone)]
+pub struct FacetsOutput {
+    sources: Vec<String>,
+    tags: Vec<String>,
+    earliest: Option<DateTime<Utc>>,
+    latest: Option<DateTime<Utc>>
+}
+
+pub struct CachedFacets {
+    cached_at: Instant,
+    facets: FacetsOutput
+}
+
+async fn distinct(state: &AppState, field: &str) -> Result<Vec<String>, AppError> {
+    let values = state.timed("distinct", field, state.collection.distinct(field, None, state.bounded(DistinctOptions::default()))).await?;
+    let mut values: Vec<String> = values.into_iter()
+        .filter_map(|v| match v {
+            Bson::String(s) => Some(s),
+            _ => None
+        })
+        .collect();
+    values.sort();
+    Ok(values)
+}
+
+// everything a client needs to build its filter controls, in one call
+pub async fn get_facets(State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
+    if let Some(cached) = &*state.facets.read().await {
+        if cached.cached_at.elapsed() < FACETS_CACHE_TTL {
+            return Ok((StatusCode::OK, Json(cached.facets.clone())));
+        }
+    }
+
+    let facets = FacetsOutput {
+        sources: distinct(&state, "source").await?,
+        tags: distinct(&state, "tags").await?,
+        earliest: find_date(&state, 1).await?,
+        latest: find_date(&state, -1).await?
+    };
+    *state.facets.write().await = Some(CachedFacets { cached_at: Instant::now(), facets: facets.clone() });
+    Ok((StatusCode::OK, Json(facets)))
+}
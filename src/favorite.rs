@@ -0,0 +1,31 @@
+use axum::{extract::{Path, State}, http::StatusCode, response::IntoResponse, Json};
+
+use crate::{audit::{AuditEntity, AuditOperation}, error::AppError, lock, parse_object_id, AppState, WheightMeasurementOutput};
+
+pub async fn add_favorite(State(state): State<AppState>, Path(id): Path<String>) -> Result<impl IntoResponse, AppError> {
+    set_favorite(state, id, true).await
+}
+
+pub async fn remove_favorite(State(state): State<AppState>, Path(id): Path<String>) -> Result<impl IntoResponse, AppError> {
+    set_favorite(state, id, false).await
+}
+
+// idempotent: a measurement already in the asked state is returned untouched, so repeating the
+// request doesn't bump its `version`; a locked one can't be changed either way
+async fn set_favorite(state: AppState, id: String, favorite: bool) -> Result<impl IntoResponse, AppError> {
+    let oid = parse_object_id(&id)?;
+    let measurement = match state.measurements.set_flag(&state, oid, "is_favorite", favorite, true).await? {
+        Some((before, after)) => {
+            state.audit(vec![AuditEntity::new(AuditOperation::Update, Some(&before), Some(&after))]).await;
+            state.invalidate_latest(&id, after.date.to_chrono()).await;
+            after
+        },
+        // nothing matched: it's locked, already in that state or doesn't exist
+        None => match state.measurements.find_by_id(&state, oid).await? {
+            Some(current) if current.is_locked => return Err(lock::locked()),
+            Some(unchanged) => unchanged,
+            None => return Err(AppError::NotFound)
+        }
+    };
+    Ok((StatusCode::OK, Json(WheightMeasurementOutput::from_entity(measurement))))
+}
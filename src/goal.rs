@@ -0,0 +1,240 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use mongodb::{bson::{doc, DateTime as BsonDateTime}, options::FindOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    daily_values::{collapsed_wheights, Daily, DailyValue}, error::AppError, latest_measurement, timezone::{local_day_start, Timezone},
+    trend, units::{OutputUnit, Unit}, AppState,
+};
+
+// within this distance of the planned line a week counts as on track
+const ON_TRACK_TOLERANCE_KG: f32 = 0.5;
+// window of the trend the pace is compared against, and how close to the required pace it
+// has to be to count as on pace
+const PACE_TREND_DAYS: i64 = 30;
+const ON_PACE_TOLERANCE_KG_PER_WEEK: f32 = 0.1;
+
+// the active weight goal, planned as a straight line from `start_wheight_kg` on `created_on`
+// to `target_wheight_kg` on `target_date`
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct GoalEntity {
+    pub start_wheight_kg: f32,
+    pub target_wheight_kg: f32,
+    pub created_on: NaiveDate,
+    pub target_date: NaiveDate
+}
+
+impl GoalEntity {
+    // planned weight at the end of `day`
+    pub fn ideal_on(&self, day: NaiveDate) -> f32 {
+        let span = (self.target_date - self.created_on).num_days().max(1) as f32;
+        let t = ((day - self.created_on).num_days() as f32 / span).clamp(0_f32, 1_f32);
+        self.start_wheight_kg + t * (self.target_wheight_kg - self.start_wheight_kg)
+    }
+
+    // how `actual` compares to the plan, ahead meaning closer to the target than planned
+    pub fn verdict(&self, ideal: f32, actual: f32) -> Verdict {
+        let losing = self.target_wheight_kg < self.start_wheight_kg;
+        let ahead_by = if losing { ideal - actual } else { actual - ideal };
+        if ahead_by > ON_TRACK_TOLERANCE_KG {
+            Verdict::Ahead
+        } else if ahead_by < -ON_TRACK_TOLERANCE_KG {
+            Verdict::Behind
+        } else {
+            Verdict::OnTrack
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Ahead,
+    Behind,
+    OnTrack
+}
+
+#[derive(Deserialize)]
+struct DailyAverageEntity {
+    date: BsonDateTime,
+    count: i64,
+    avg_wheight_kg: f32
+}
+
+#[derive(Serialize)]
+struct BurndownWeek {
+    week_start: NaiveDate,
+    week_end: NaiveDate,
+    ideal_wheight_kg: f32,
+    // null for weeks without measurements, including those still ahead
+    actual_wheight_kg: Option<f32>,
+    verdict: Option<Verdict>
+}
+
+#[derive(Serialize)]
+struct BurndownOutput {
+    start_wheight_kg: f32,
+    target_wheight_kg: f32,
+    created_on: NaiveDate,
+    target_date: NaiveDate,
+    target_date_passed: bool,
+    // verdict of the latest week with measurements
+    verdict: Option<Verdict>,
+    unit: Unit,
+    weeks: Vec<BurndownWeek>
+}
+
+// each day of `tz` in `[start, end)` with its average weight and number of measurements; in UTC
+// from the daily aggregates, elsewhere from the measurements themselves
+async fn daily_averages(state: &AppState, start: NaiveDate, end: NaiveDate, tz: Tz) -> Result<Vec<(NaiveDate, f32, i64)>, AppError> {
+    let filter = doc! { "date": {
+        "$gte": BsonDateTime::from_chrono(local_day_start(start, tz)),
+        "$lt": BsonDateTime::from_chrono(local_day_start(end, tz))
+    } };
+    if tz != Tz::UTC {
+        let days = collapsed_wheights(state, filter, Daily { value: DailyValue::Avg, tz }).await?;
+        return Ok(days.into_iter().map(|p| (p.day, p.wheight_kg as f32, p.count)).collect());
+    }
+    let options = FindOptions::builder().projection(doc! { "date": 1, "count": 1, "avg_wheight_kg": 1 }).build();
+    let mut cursor = state.timed("find", &filter, state.aggregates.clone_with_type::<DailyAverageEntity>().find(filter.clone(), state.bounded(options))).await?;
+    let mut days = Vec::new();
+    while cursor.advance().await? {
+        let day = cursor.deserialize_current()?;
+        days.push((day.date.to_chrono().date_naive(), day.avg_wheight_kg, day.count));
+    }
+    Ok(days)
+}
+
+// planned versus actual weight for each week of the active goal, counted in the calendar of
+// `tz`, the actual being the week's average
+pub async fn get_goal_burndown(State(state): State<AppState>, Timezone(tz): Timezone, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let Some(goal) = state.profile().await?.goal else {
+        return Err(AppError::NotFound);
+    };
+
+    // per week since `created_on`, the total weight and number of measurements
+    let weeks_count = ((goal.target_date - goal.created_on).num_days() / 7 + 1) as usize;
+    let mut totals = vec![(0_f32, 0_i64); weeks_count];
+    for (day, avg_wheight_kg, count) in daily_averages(&state, goal.created_on, goal.target_date.succ_opt().unwrap(), tz).await? {
+        let week = ((day - goal.created_on).num_days() / 7) as usize;
+        if let Some(total) = totals.get_mut(week) {
+            total.0 += avg_wheight_kg * count as f32;
+            total.1 += count;
+        }
+    }
+
+    let weeks: Vec<BurndownWeek> = totals.iter().enumerate()
+        .map(|(i, &(sum, count))| {
+            let week_start = goal.created_on + Duration::days(7 * i as i64);
+            let week_end = (week_start + Duration::days(6)).min(goal.target_date);
+            let ideal = goal.ideal_on(week_end);
+            let actual = (count > 0).then(|| sum / count as f32);
+            BurndownWeek {
+                week_start,
+                week_end,
+                ideal_wheight_kg: unit.convert_kg(ideal),
+                actual_wheight_kg: actual.map(|a| unit.convert_kg(a)),
+                verdict: actual.map(|a| goal.verdict(ideal, a))
+            }
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(BurndownOutput {
+        start_wheight_kg: unit.convert_kg(goal.start_wheight_kg),
+        target_wheight_kg: unit.convert_kg(goal.target_wheight_kg),
+        created_on: goal.created_on,
+        target_date: goal.target_date,
+        target_date_passed: goal.target_date < Utc::now().with_timezone(&tz).date_naive(),
+        verdict: weeks.iter().rev().find_map(|w| w.verdict),
+        unit,
+        weeks
+    })))
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum GoalStatus {
+    Active,
+    // the latest measurement is at or past the target
+    Achieved,
+    // the target date is behind us without the target being reached
+    TargetDatePassed
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum PaceVerdict {
+    Faster,
+    Slower,
+    OnPace
+}
+
+#[derive(Serialize)]
+struct ToGoalOutput {
+    status: GoalStatus,
+    current_wheight_kg: f32,
+    target_wheight_kg: f32,
+    target_date: NaiveDate,
+    // both only while the goal is active
+    days_left: Option<i64>,
+    // negative to lose, positive to gain
+    required_kg_per_week: Option<f32>,
+    // over the last `PACE_TREND_DAYS`, null with fewer than two measurements in them
+    trend_kg_per_week: Option<f32>,
+    // how the trend compares to the required pace, towards the target
+    verdict: Option<PaceVerdict>,
+    unit: Unit
+}
+
+// the weekly change still needed to reach the active goal by its date, against the current
+// trend
+pub async fn get_to_goal(State(state): State<AppState>, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let Some(goal) = state.profile().await?.goal else {
+        return Err(AppError::NotFound);
+    };
+    let Some(latest) = latest_measurement(&state).await? else {
+        return Err(AppError::NotFound);
+    };
+    let trend = trend::wheight_trend(&state, PACE_TREND_DAYS).await?;
+
+    let (current, target) = (latest.wheight_kg, goal.target_wheight_kg);
+    let losing = target < goal.start_wheight_kg;
+    let today = Utc::now().date_naive();
+    let status = if (losing && current <= target) || (!losing && current >= target) {
+        GoalStatus::Achieved
+    } else if goal.target_date < today {
+        GoalStatus::TargetDatePassed
+    } else {
+        GoalStatus::Active
+    };
+
+    let days_left = (status == GoalStatus::Active).then(|| (goal.target_date - today).num_days());
+    // a target due today still leaves today's day to get there
+    let required = days_left.map(|days| (target - current) / days.max(1) as f32 * 7_f32);
+    let trend_kg_per_week = trend.map(|t| t.slope_kg_per_week);
+    let verdict = required.zip(trend_kg_per_week).map(|(required, trend)| {
+        // both measured towards the target, so a trend going the wrong way is always slower
+        let (required, trend) = (required.abs(), trend * required.signum());
+        if trend > required + ON_PACE_TOLERANCE_KG_PER_WEEK {
+            PaceVerdict::Faster
+        } else if trend < required - ON_PACE_TOLERANCE_KG_PER_WEEK {
+            PaceVerdict::Slower
+        } else {
+            PaceVerdict::OnPace
+        }
+    });
+
+    Ok((StatusCode::OK, Json(ToGoalOutput {
+        status,
+        current_wheight_kg: unit.convert_kg(current),
+        target_wheight_kg: unit.convert_kg(target),
+        target_date: goal.target_date,
+        days_left,
+        required_kg_per_week: required.map(|r| unit.convert_kg(r)),
+        trend_kg_per_week: trend_kg_per_week.map(|t| unit.convert_kg(t)),
+        verdict,
+        unit
+    })))
+}
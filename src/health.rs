@@ -0,0 +1,76 @@
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use mongodb::{bson::{doc, oid::ObjectId, DateTime as BsonDateTime, Document}, Collection};
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+
+#[derive(Deserialize)]
+pub struct ReadyQuery {
+    // also prove the database takes writes, not just that the server answers
+    #[serde(default)]
+    deep: bool
+}
+
+#[derive(Serialize)]
+struct ReadyOutput {
+    status: &'static str,
+    // the step of the deep check that failed, driver details only go to the logs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failed: Option<&'static str>
+}
+
+// deletes the sentinel when dropped unless `disarm`ed, so a failed delete or a request cancelled
+// halfway through still doesn't leave it behind
+struct Sentinel {
+    collection: Collection<Document>,
+    id: ObjectId,
+    armed: bool
+}
+
+impl Sentinel {
+    fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for Sentinel {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let (collection, id) = (self.collection.clone(), self.id);
+        tokio::spawn(async move {
+            if let Err(e) = collection.delete_one(doc! { "_id": id }, None).await {
+                tracing::error!("Error cleaning up health-check sentinel {}: {}", id, e);
+            }
+        });
+    }
+}
+
+// readiness probe; with `?deep=true` it also inserts and deletes a sentinel in `_healthcheck`,
+// catching credentials that can read but not write
+pub async fn get_ready(State(state): State<AppState>, Query(query): Query<ReadyQuery>) -> impl IntoResponse {
+    if !query.deep {
+        return (StatusCode::OK, Json(ReadyOutput { status: "ok", failed: None }));
+    }
+
+    // armed before the insert, whose error may come after the document was written anyway
+    let sentinel = Sentinel { collection: state.healthcheck.clone(), id: ObjectId::new(), armed: true };
+    let id = sentinel.id;
+    let unavailable = |step: &'static str, e: mongodb::error::Error| {
+        tracing::error!("Deep readiness check failed to {} the sentinel: {}", step, e);
+        (StatusCode::SERVICE_UNAVAILABLE, Json(ReadyOutput { status: "unavailable", failed: Some(step) }))
+    };
+
+    let document = doc! { "_id": id, "at": BsonDateTime::now() };
+    if let Err(e) = state.timed("insert_one", &id.to_hex(), state.healthcheck.insert_one(document, None)).await {
+        return unavailable("insert", e);
+    }
+    match state.timed("delete_one", &id.to_hex(), state.healthcheck.delete_one(doc! { "_id": id }, None)).await {
+        Ok(_) => {
+            sentinel.disarm();
+            (StatusCode::OK, Json(ReadyOutput { status: "ok", failed: None }))
+        },
+        Err(e) => unavailable("delete", e)
+    }
+}
@@ -0,0 +1,117 @@
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use mongodb::{bson::doc, options::FindOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::{correlation::{metric_value, parse_metric}, error::AppError, units::{OutputUnit, Unit}, AppState};
+
+const DEFAULT_BINS: usize = 20;
+const MAX_BINS: usize = 200;
+
+#[derive(Deserialize)]
+pub struct HistogramQuery {
+    // any of the correlation metrics, `wheight_kg` by default
+    metric: Option<String>,
+    bins: Option<usize>
+}
+
+#[derive(Serialize)]
+struct Bin {
+    // `high` is exclusive except in the last bin, which ends at the largest value
+    low: f64,
+    high: f64,
+    count: usize
+}
+
+#[derive(Serialize)]
+struct HistogramOutput {
+    metric: &'static str,
+    // only for the `_kg` metrics, the others have units of their own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<Unit>,
+    count: usize,
+    // measurements missing the metric (reported as 0 by scales without it)
+    skipped: usize,
+    // only the most recent `AGG_MAX_DOCS` measurements were counted
+    approximate: bool,
+    // equal-width, from the smallest to the largest value; empty without measurements
+    bins: Vec<Bin>
+}
+
+// `values` counted into `bins` equal-width bins from the smallest to the largest; a single
+// distinct value fills one bin of no width, and no values give no bins
+fn binned(values: &[f64], bins: usize) -> Vec<Bin> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let (min, max) = values.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(*v), hi.max(*v)));
+    let width = (max - min) / bins as f64;
+    let bins = if width > 0_f64 { bins } else { 1 };
+    let mut output: Vec<Bin> = (0..bins).map(|i| Bin {
+        low: min + width * i as f64,
+        high: if i + 1 == bins { max } else { min + width * (i + 1) as f64 },
+        count: 0
+    }).collect();
+    for value in values {
+        let i = if width > 0_f64 { ((value - min) / width) as usize } else { 0 };
+        output[i.min(bins - 1)].count += 1;
+    }
+    output
+}
+
+// how a metric's values are distributed, counted into `bins` equal-width bins
+pub async fn get_histogram(State(state): State<AppState>, Query(query): Query<HistogramQuery>, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let metric = parse_metric(query.metric.as_deref().unwrap_or("wheight_kg"))?;
+    let bins = query.bins.unwrap_or(DEFAULT_BINS);
+    if !(1..=MAX_BINS).contains(&bins) {
+        return Err(AppError::BadRequest(format!("bins must be between 1 and {}", MAX_BINS)));
+    }
+
+    let unit = metric.ends_with("_kg").then_some(unit);
+    let (filter, approximate) = state.capped(doc! {}).await?;
+    let mut cursor = state.timed("find", &filter, state.collection.find(filter.clone(), state.bounded(FindOptions::default()))).await?;
+    let (mut values, mut skipped) = (Vec::new(), 0);
+    while cursor.advance().await? {
+        let value = metric_value(&cursor.deserialize_current()?, metric);
+        if value == 0_f64 {
+            skipped += 1;
+            continue;
+        }
+        values.push(unit.map_or(value, |unit| unit.convert_kg(value as f32) as f64));
+    }
+
+    let output = binned(&values, bins);
+    Ok((StatusCode::OK, Json(HistogramOutput { metric, unit, count: values.len(), skipped, approximate, bins: output })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(bins: &[Bin]) -> Vec<usize> {
+        bins.iter().map(|b| b.count).collect()
+    }
+
+    #[test]
+    fn values_fall_into_equal_width_bins() {
+        let bins = binned(&[80.0, 81.0, 82.5, 84.0], 4);
+        assert_eq!(counts(&bins), vec![1, 1, 1, 1]);
+        assert_eq!((bins[0].low, bins[0].high), (80.0, 81.0));
+        assert_eq!((bins[3].low, bins[3].high), (83.0, 84.0));
+    }
+
+    #[test]
+    fn a_bin_edge_belongs_to_the_upper_bin_and_the_maximum_to_the_last() {
+        let bins = binned(&[0.0, 5.0, 10.0], 2);
+        assert_eq!(counts(&bins), vec![1, 2]);
+    }
+
+    #[test]
+    fn degenerate_inputs() {
+        assert!(binned(&[], 5).is_empty());
+        let single = binned(&[80.0], 5);
+        assert_eq!(counts(&single), vec![1]);
+        assert_eq!((single[0].low, single[0].high), (80.0, 80.0));
+        assert_eq!(counts(&binned(&[80.0, 80.0, 80.0], 5)), vec![3]);
+        assert_eq!(counts(&binned(&[80.0, 90.0], 1)), vec![2]);
+    }
+}
@@ -0,0 +1,46 @@
+use std::convert::Infallible;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+
+// languages human-readable labels come in, English for anything else
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    En,
+    Pt,
+    Es
+}
+
+impl Language {
+    // first supported language in `Accept-Language` order, by primary subtag (`pt-BR` is `pt`);
+    // quality values aren't weighed
+    fn from_accept_language(accept: &str) -> Option<Self> {
+        accept.split(',')
+            .map(|range| range.split(';').next().unwrap_or("").trim())
+            .find_map(|tag| match tag.split('-').next().unwrap_or("").to_ascii_lowercase().as_str() {
+                "en" => Some(Language::En),
+                "pt" => Some(Language::Pt),
+                "es" => Some(Language::Es),
+                _ => None
+            })
+    }
+}
+
+pub struct AcceptLanguage(pub Language);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AcceptLanguage {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let language = parts.headers.get(header::ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(Language::from_accept_language)
+            .unwrap_or_default();
+        Ok(AcceptLanguage(language))
+    }
+}
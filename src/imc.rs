@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use mongodb::{bson::{doc, oid::ObjectId, Document}, options::{CountOptions, FindOptions}};
+use serde::Serialize;
+
+use crate::{
+    admin::Admin, audit::{AuditEntity, AuditOperation}, error::AppError, i18n::Language, latest_measurement,
+    target::{TargetBand, TargetStatus}, units::{OutputUnit, Unit}, validation::Warning, AppState, WheightMeasurementInput,
+};
+
+// WHO adult BMI classes; these keys are part of the API contract, only the labels are localized
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImcCategory {
+    Underweight,
+    Normal,
+    Overweight,
+    Obese
+}
+
+// upper bounds (exclusive) of the classes below obese
+const UNDERWEIGHT_BELOW: f32 = 18.5;
+const NORMAL_BELOW: f32 = 25_f32;
+const OVERWEIGHT_BELOW: f32 = 30_f32;
+// highest normal `imc` at the one decimal it's stored with, the top of the healthy range
+const NORMAL_MAX: f32 = 24.9;
+
+impl ImcCategory {
+    // `None` for a missing (0) or non-finite `imc`
+    pub fn from_imc(imc: f32) -> Option<Self> {
+        if !imc.is_finite() || imc <= 0_f32 {
+            return None;
+        }
+        Some(match imc {
+            i if i < UNDERWEIGHT_BELOW => ImcCategory::Underweight,
+            i if i < NORMAL_BELOW => ImcCategory::Normal,
+            i if i < OVERWEIGHT_BELOW => ImcCategory::Overweight,
+            _ => ImcCategory::Obese
+        })
+    }
+
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "underweight" => Ok(ImcCategory::Underweight),
+            "normal" => Ok(ImcCategory::Normal),
+            "overweight" => Ok(ImcCategory::Overweight),
+            "obese" => Ok(ImcCategory::Obese),
+            other => Err(AppError::BadRequest(format!("Unknown bmi_category '{}', expected underweight, normal, overweight or obese", other)))
+        }
+    }
+
+    // `imc` condition matching the class, the same bounds `from_imc` uses; a missing `imc`
+    // matches none of them
+    pub fn imc_filter(self) -> Document {
+        let (low, high) = match self {
+            ImcCategory::Underweight => (None, Some(UNDERWEIGHT_BELOW)),
+            ImcCategory::Normal => (Some(UNDERWEIGHT_BELOW), Some(NORMAL_BELOW)),
+            ImcCategory::Overweight => (Some(NORMAL_BELOW), Some(OVERWEIGHT_BELOW)),
+            ImcCategory::Obese => (Some(OVERWEIGHT_BELOW), None)
+        };
+        let mut range = doc! { "$gt": 0 };
+        if let Some(low) = low {
+            range.insert("$gte", low);
+        }
+        if let Some(high) = high {
+            range.insert("$lt", high);
+        }
+        range
+    }
+
+    pub fn label(self, language: Language) -> &'static str {
+        match (self, language) {
+            (ImcCategory::Underweight, Language::En) => "Underweight",
+            (ImcCategory::Underweight, Language::Pt) => "Abaixo do peso",
+            (ImcCategory::Underweight, Language::Es) => "Bajo peso",
+            (ImcCategory::Normal, Language::En) => "Normal weight",
+            (ImcCategory::Normal, Language::Pt) => "Peso normal",
+            (ImcCategory::Normal, Language::Es) => "Peso normal",
+            (ImcCategory::Overweight, Language::En) => "Overweight",
+            (ImcCategory::Overweight, Language::Pt) => "Sobrepeso",
+            (ImcCategory::Overweight, Language::Es) => "Sobrepeso",
+            (ImcCategory::Obese, Language::En) => "Obesity",
+            (ImcCategory::Obese, Language::Pt) => "Obesidade",
+            (ImcCategory::Obese, Language::Es) => "Obesidad"
+        }
+    }
+}
+
+// `wheight_kg / height_m²` to one decimal, like the scales report it
+pub fn compute_imc(wheight_kg: f32, height_cm: f32) -> f32 {
+    let height_m = height_cm / 100_f32;
+    (wheight_kg / (height_m * height_m) * 10_f32).round() / 10_f32
+}
+
+impl AppState {
+    // with a profile height, a submitted `imc` is replaced by the computed one, and a warning
+    // tells the client when the two are further apart than `IMC_TOLERANCE`, which usually
+    // means a wrong height on the scale or a client bug; a missing (0) `imc` is left alone
+    pub async fn reconcile_imc(&self, input: &mut WheightMeasurementInput) -> Result<Option<Warning>, AppError> {
+        if input.imc <= 0_f32 {
+            return Ok(None);
+        }
+        let Some(height_cm) = self.profile().await?.height_cm else {
+            return Ok(None);
+        };
+        let computed = compute_imc(input.wheight_kg, height_cm);
+        let submitted = std::mem::replace(&mut input.imc, computed);
+        Ok(((submitted - computed).abs() > self.config.imc_tolerance).then(|| Warning {
+            field: "imc",
+            message: format!("imc {} doesn't match the {} computed from wheight_kg and the profile's height_cm {}, {} was stored", submitted, computed, height_cm, computed)
+        }))
+    }
+}
+
+#[derive(Serialize)]
+struct RecalculateImcOutput {
+    corrected: u64,
+    // those whose `imc` already matched
+    unchanged: u64,
+    // measurements left as they were, all of them when the profile has no `height_cm`, else the
+    // locked ones whose `imc` is off
+    skipped: u64
+}
+
+// rewrites every stored `imc` that doesn't match `wheight_kg` and the profile height, to one
+// decimal like the scales report it; measurements already right, or locked, aren't touched
+pub async fn recalculate_imc(_: Admin, State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
+    let Some(height_cm) = state.profile().await?.height_cm else {
+        let skipped = state.timed("count_documents", "all", state.collection.count_documents(None, state.bounded_primary(CountOptions::default()))).await?;
+        return Ok((StatusCode::OK, Json(RecalculateImcOutput { corrected: 0, unchanged: 0, skipped })));
+    };
+
+    let height_m = height_cm as f64 / 100_f64;
+    let imc = doc! { "$round": [{ "$divide": ["$wheight_kg", height_m * height_m] }, 1] };
+    // stored as `f32`, so an exact comparison would find most of them off by a hair
+    let wrong = doc! { "$expr": { "$gte": [{ "$abs": { "$subtract": ["$imc", imc.clone()] } }, 0.05] } };
+    let total = state.timed("count_documents", "all", state.collection.count_documents(None, state.bounded_primary(CountOptions::default()))).await?;
+    let mut locked = wrong.clone();
+    locked.insert("is_locked", true);
+    let skipped = state.timed("count_documents", &locked, state.collection.count_documents(locked.clone(), state.bounded_primary(CountOptions::default()))).await?;
+    let mut wrong = wrong;
+    wrong.insert("is_locked", doc! { "$ne": true });
+
+    // the documents about to change, for their audit entries; the update is limited to them so
+    // one written meanwhile isn't changed without a trace
+    let mut cursor = state.timed("find", &wrong, state.collection.find(wrong.clone(), state.bounded_primary(FindOptions::default()))).await?;
+    let mut before = HashMap::new();
+    while cursor.advance().await? {
+        let m = cursor.deserialize_current()?;
+        before.insert(m._id, m);
+    }
+    let ids: Vec<ObjectId> = before.keys().copied().collect();
+    let mut filter = doc! { "_id": { "$in": &ids } };
+    filter.extend(wrong);
+    let update = vec![doc! { "$set": {
+        "imc": imc,
+        "updated_at": "$$NOW",
+        "version": { "$add": [{ "$ifNull": ["$version", 0] }, 1] }
+    } }];
+    let result = state.timed("update_many", &filter, state.collection.update_many(filter.clone(), update, None)).await?;
+
+    // read back rather than rebuilt, the server did the rounding and set `updated_at`
+    let corrected = doc! { "_id": { "$in": &ids } };
+    let mut cursor = state.timed("find", &corrected, state.collection.find(corrected.clone(), state.bounded_primary(FindOptions::default()))).await?;
+    let mut entries = Vec::new();
+    while cursor.advance().await? {
+        let after = cursor.deserialize_current()?;
+        if let Some(before) = before.get(&after._id).filter(|b| b.version != after.version) {
+            entries.push(AuditEntity::new(AuditOperation::Update, Some(before), Some(&after)));
+        }
+    }
+    state.audit(entries).await;
+
+    *state.latest.write().await = None;
+    Ok((StatusCode::OK, Json(RecalculateImcOutput {
+        corrected: result.modified_count,
+        unchanged: total.saturating_sub(result.modified_count + skipped),
+        skipped
+    })))
+}
+
+// the weights whose `imc` is in the normal class at `height_cm`, from where underweight ends
+// to the highest normal `imc`, `NORMAL_MAX`
+pub fn healthy_band(height_cm: f32) -> TargetBand {
+    let height_m = height_cm / 100_f32;
+    TargetBand { low_kg: UNDERWEIGHT_BELOW * height_m * height_m, high_kg: NORMAL_MAX * height_m * height_m }
+}
+
+#[derive(Serialize)]
+struct HealthyRangeOutput {
+    min_kg: f32,
+    max_kg: f32,
+    height_cm: f32,
+    unit: Unit,
+    // both null while there are no measurements
+    latest_wheight_kg: Option<f32>,
+    latest_status: Option<TargetStatus>
+}
+
+// the healthy weight range for the profile height and where the latest weight sits in it
+pub async fn get_healthy_range(State(state): State<AppState>, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let Some(height_cm) = state.profile().await?.height_cm else {
+        return Err(AppError::Validation("The profile has no height_cm, set it with PUT /profile/height".to_string()));
+    };
+    let band = healthy_band(height_cm);
+    let latest = latest_measurement(&state).await?;
+    Ok((StatusCode::OK, Json(HealthyRangeOutput {
+        min_kg: unit.convert_kg(band.low_kg),
+        max_kg: unit.convert_kg(band.high_kg),
+        height_cm,
+        unit,
+        latest_wheight_kg: latest.as_ref().map(|m| unit.convert_kg(m.wheight_kg)),
+        latest_status: latest.map(|m| band.status(m.wheight_kg).in_unit(unit))
+    })))
+}
@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use mongodb::{bson::{doc, Bson, Document}, Collection, IndexModel};
+use serde::Serialize;
+
+use crate::{admin::Admin, audit::AuditEntity, error::AppError, AppState, WheightMeasurementEntity};
+
+// indexes the queries rely on
+fn expected_indexes() -> Vec<IndexModel> {
+    vec![
+        IndexModel::builder().keys(doc! { "date": -1 }).build(),
+        IndexModel::builder().keys(doc! { "tags": 1 }).build(),
+    ]
+}
+
+// a measurement's history and the lookup of its deletion, both by `measurement_id`
+fn expected_audit_indexes() -> Vec<IndexModel> {
+    vec![IndexModel::builder().keys(doc! { "measurement_id": 1, "at": 1 }).build()]
+}
+
+// creating an existing index is a no-op, and a failure here only costs performance, so it is
+// logged rather than stopping startup
+pub async fn ensure_indexes(collection: Collection<WheightMeasurementEntity>, audit: Collection<AuditEntity>) {
+    match collection.create_indexes(expected_indexes(), None).await {
+        Ok(r) => tracing::info!("Indexes ready: {:?}", r.index_names),
+        Err(e) => tracing::warn!("Error creating indexes: {}", e)
+    }
+    match audit.create_indexes(expected_audit_indexes(), None).await {
+        Ok(r) => tracing::info!("Audit indexes ready: {:?}", r.index_names),
+        Err(e) => tracing::warn!("Error creating audit indexes: {}", e)
+    }
+}
+
+#[derive(Serialize)]
+struct ReindexOutput {
+    indexes: Vec<String>,
+    // those that were missing before this run
+    created: Vec<String>,
+    document_count: i64,
+    storage_size_bytes: i64,
+    total_index_size_bytes: i64,
+    index_sizes_bytes: BTreeMap<String, i64>
+}
+
+// `collStats` reports sizes as whichever numeric type fits them
+fn number(stats: &Document, key: &str) -> i64 {
+    match stats.get(key) {
+        Some(Bson::Int32(n)) => *n as i64,
+        Some(Bson::Int64(n)) => *n,
+        Some(Bson::Double(n)) => *n as i64,
+        _ => 0
+    }
+}
+
+// (re)creates the expected indexes on the measurements and the audit trail and reports the collection's size and
+// the size of each index, to confirm they exist in production and diagnose slow queries
+pub async fn reindex(_: Admin, State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
+    let collection = &state.collection;
+    let before = state.timed("list_index_names", collection.name(), collection.list_index_names()).await?;
+    tracing::info!("Indexes before reindex: {:?}", before);
+    state.timed("create_indexes", collection.name(), collection.create_indexes(expected_indexes(), None)).await?;
+    state.timed("create_indexes", state.audit.name(), state.audit.create_indexes(expected_audit_indexes(), None)).await?;
+    let after = state.timed("list_index_names", collection.name(), collection.list_index_names()).await?;
+    tracing::info!("Indexes after reindex: {:?}", after);
+
+    let database = collection.client().database(&collection.namespace().db);
+    let command = doc! { "collStats": collection.name() };
+    let stats = state.timed("collStats", &command, database.run_command(command.clone(), None)).await?;
+    let index_sizes_bytes = match stats.get_document("indexSizes") {
+        Ok(sizes) => sizes.keys().map(|name| (name.clone(), number(sizes, name))).collect(),
+        Err(_) => BTreeMap::new()
+    };
+
+    Ok((StatusCode::OK, Json(ReindexOutput {
+        created: after.iter().filter(|name| !before.contains(name)).cloned().collect(),
+        indexes: after,
+        document_count: number(&stats, "count"),
+        storage_size_bytes: number(&stats, "storageSize"),
+        total_index_size_bytes: number(&stats, "totalIndexSize"),
+        index_sizes_bytes
+    })))
+}
@@ -0,0 +1,35 @@
+use axum::{extract::{Path, State}, http::StatusCode, response::IntoResponse, Json};
+
+use crate::{admin::Admin, audit::{AuditEntity, AuditOperation}, error::AppError, parse_object_id, AppState, WheightMeasurementOutput};
+
+pub fn locked() -> AppError {
+    AppError::Locked("Measurement is locked, an admin has to unlock it before it can be changed".to_string())
+}
+
+// anyone can lock a measurement to protect it from accidental edits
+pub async fn lock_measurement(State(state): State<AppState>, Path(id): Path<String>) -> Result<impl IntoResponse, AppError> {
+    set_locked(state, id, true).await
+}
+
+// unlocking is what makes a measurement editable again, so it takes the admin token
+pub async fn unlock_measurement(_: Admin, State(state): State<AppState>, Path(id): Path<String>) -> Result<impl IntoResponse, AppError> {
+    set_locked(state, id, false).await
+}
+
+// idempotent like the favorite toggle: a measurement already in the asked state is returned
+// untouched
+async fn set_locked(state: AppState, id: String, locked: bool) -> Result<impl IntoResponse, AppError> {
+    let oid = parse_object_id(&id)?;
+    let measurement = match state.measurements.set_flag(&state, oid, "is_locked", locked, false).await? {
+        Some((before, after)) => {
+            state.audit(vec![AuditEntity::new(AuditOperation::Update, Some(&before), Some(&after))]).await;
+            state.invalidate_latest(&id, after.date.to_chrono()).await;
+            after
+        },
+        None => match state.measurements.find_by_id(&state, oid).await? {
+            Some(unchanged) => unchanged,
+            None => return Err(AppError::NotFound)
+        }
+    };
+    Ok((StatusCode::OK, Json(WheightMeasurementOutput::from_entity(measurement))))
+}
@@ -0,0 +1,46 @@
+use chrono::SecondsFormat;
+use mongodb::bson::{Bson, DateTime as BsonDateTime, Document};
+
+pub fn bson_datetime_to_rfc3339(date: BsonDateTime) -> String {
+    date.to_chrono().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+// BSON's `Debug` prints dates as `DateTime(2024-01-01 0:00:00.0 +00:00:00)`, logs get JSON
+// with every date as an RFC-3339 string instead
+fn readable(value: &Bson) -> serde_json::Value {
+    match value {
+        Bson::DateTime(date) => serde_json::Value::String(bson_datetime_to_rfc3339(*date)),
+        Bson::Document(document) => serde_json::Value::Object(document.iter().map(|(k, v)| (k.clone(), readable(v))).collect()),
+        Bson::Array(values) => serde_json::Value::Array(values.iter().map(readable).collect()),
+        other => other.clone().into_relaxed_extjson()
+    }
+}
+
+// how a filter, pipeline or other operation summary is written to the logs
+pub trait LogSummary {
+    fn log_summary(&self) -> String;
+}
+
+impl LogSummary for Document {
+    fn log_summary(&self) -> String {
+        readable(&Bson::Document(self.clone())).to_string()
+    }
+}
+
+impl LogSummary for Vec<Document> {
+    fn log_summary(&self) -> String {
+        readable(&Bson::Array(self.iter().cloned().map(Bson::Document).collect())).to_string()
+    }
+}
+
+macro_rules! display_summary {
+    ($($t:ty),*) => {
+        $(impl LogSummary for $t {
+            fn log_summary(&self) -> String {
+                self.to_string()
+            }
+        })*
+    };
+}
+
+display_summary!(str, String, u32, usize);
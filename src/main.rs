@@ -1,16 +1,141 @@
 use axum::{
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
-    Json, Router, extract::Path,
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json, extract::{Path, Query, State},
+    middleware::{self, Next},
+    body::HttpBody,
 };
 use serde::{Deserialize, Serialize};
+use tower_http::catch_panic::CatchPanicLayer;
 use tower_http::cors::{Any, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use std::env;
-use tracing::{info, Level};
+use std::net::SocketAddr;
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use tracing::{Instrument, Level};
 use tracing_subscriber::FmtSubscriber;
-use chrono::{DateTime, Utc};
-use mongodb::{bson::{doc, Bson, self, DateTime as BsonDateTime}, options::{ClientOptions, ServerApi, ServerApiVersion}, Client, Collection};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use mongodb::{bson::{doc, self, DateTime as BsonDateTime}, options::{ClientOptions, CollectionOptions, CountOptions, FindOneOptions, FindOptions, ServerApi, ServerApiVersion, WriteConcern}, Client, Collection, Database};
+
+mod admin;
+mod agg_cap;
+mod aggregates;
+mod analytics;
+mod body_log;
+mod concurrency;
+mod correlation;
+mod audit;
+mod bulk_tag;
+mod config;
+mod daily_values;
+mod datasets;
+mod envelope;
+mod error;
+mod export;
+mod facets;
+mod favorite;
+mod health;
+mod histogram;
+mod goal;
+mod i18n;
+mod lock;
+mod imc;
+mod indexes;
+mod log_format;
+mod max_time;
+mod migrations;
+mod moving_average;
+mod negotiation;
+mod noise;
+mod number_format;
+mod percent_format;
+mod plateau;
+mod quality;
+mod repository;
+mod reschedule;
+mod retention;
+mod rounding;
+mod profile;
+mod routes;
+mod search;
+mod seed;
+mod single_flight;
+mod slow_query;
+mod summary;
+mod sync;
+mod target;
+mod timezone;
+mod trend;
+mod units;
+mod validation;
+mod volatility;
+
+use concurrency::ConcurrencyLimit;
+use config::Config;
+use envelope::Pagination;
+use aggregates::DailyAggregateEntity;
+use audit::{AuditEntity, AuditOperation};
+use error::AppError;
+use facets::CachedFacets;
+use i18n::{AcceptLanguage, Language};
+use imc::ImcCategory;
+use log_format::LogSummary;
+use negotiation::Negotiated;
+use noise::NoiseThresholds;
+use profile::ProfileEntity;
+use quality::Quality;
+use repository::MeasurementRepository;
+use units::{OutputUnit, Unit};
+use validation::{FieldErrors, Validate, ValidatedJson, Warning};
+
+// how long the cached latest measurement is trusted before going back to the DB
+const LATEST_CACHE_TTL: Duration = Duration::from_secs(30);
+
+#[derive(Clone)]
+struct AppState {
+    config: Arc<Config>,
+    collection: Collection<WheightMeasurementEntity>,
+    // `collection` outside tests, for the operations that go through the repository
+    measurements: Arc<dyn MeasurementRepository + Send + Sync>,
+    profiles: Collection<ProfileEntity>,
+    aggregates: Collection<DailyAggregateEntity>,
+    audit: Collection<AuditEntity>,
+    healthcheck: Collection<bson::Document>,
+    latest: Arc<RwLock<Option<CachedLatest>>>,
+    profile: Arc<RwLock<Option<ProfileEntity>>>,
+    facets: Arc<RwLock<Option<CachedFacets>>>
+}
+
+struct CachedLatest {
+    cached_at: Instant,
+    measurement: WheightMeasurementOutput
+}
+
+impl AppState {
+    async fn cached_latest(&self) -> Option<WheightMeasurementOutput> {
+        match &*self.latest.read().await {
+            Some(c) if c.cached_at.elapsed() < LATEST_CACHE_TTL => Some(c.measurement.clone()),
+            _ => None
+        }
+    }
+
+    async fn cache_latest(&self, measurement: WheightMeasurementOutput) {
+        *self.latest.write().await = Some(CachedLatest { cached_at: Instant::now(), measurement });
+    }
+
+    // drops the cached latest measurement if a write to `id` at `date` could have replaced it
+    async fn invalidate_latest(&self, id: &str, date: DateTime<Utc>) {
+        let mut latest = self.latest.write().await;
+        if latest.as_ref().is_some_and(|c| c.measurement.id == id || date >= c.measurement.date) {
+            *latest = None;
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() {
@@ -27,31 +152,247 @@ async fn main() {
     tracing::subscriber::set_global_default(subscriber)
         .expect("setting default subscriber failed");
 
-    let cors = CorsLayer::new()
-        // allow `GET` and `POST` when accessing the resource
-        .allow_methods(Any)
-        .allow_headers(Any)
-        // allow requests from any origin
-        .allow_origin(Any);
+    env::set_var("mongoDb.connectionString", "mongodb://127.0.0.1:27017/?directConnection=true&serverSelectionTimeoutMS=2000");
+
+    let config = Config::from_env();
+    tracing::info!("Reads prefer {}, writes go to the primary with w={}", config.read_preference, config.write_concern);
+    if config.log_bodies {
+        tracing::warn!("LOG_BODIES is on: every JSON request and response body is logged, which is verbose and may expose personal data, don't leave it on outside development");
+    }
+    let write_concern = config.write_concern.write_concern();
+    let cors = cors_layer(&config.cors_origins);
+    let database = get_database("fabdev").await.expect("Error connecting to the database");
+    let collection = get_collection::<WheightMeasurementEntity>(&database, "Wheights", &write_concern);
+    let profiles = get_collection::<ProfileEntity>(&database, "Profile", &write_concern);
+    let aggregates = get_collection::<DailyAggregateEntity>(&database, "daily_aggregates", &write_concern);
+    let audit = get_collection::<AuditEntity>(&database, "audit", &write_concern);
+    let healthcheck = get_collection::<bson::Document>(&database, "_healthcheck", &write_concern);
+    let in_flight = Arc::new(AtomicUsize::new(0));
+
+    tokio::spawn(indexes::ensure_indexes(collection.clone(), audit.clone()));
+    let migrations = get_collection::<bson::Document>(&database, "migrations", &write_concern);
+    tokio::spawn(migrations::run(collection.clone(), migrations));
+
+    let state = AppState {
+        config: Arc::new(config.clone()),
+        measurements: Arc::new(collection.clone()),
+        collection,
+        profiles,
+        aggregates,
+        audit,
+        healthcheck,
+        latest: Arc::new(RwLock::new(None)),
+        profile: Arc::new(RwLock::new(None)),
+        facets: Arc::new(RwLock::new(None))
+    };
+
+    tokio::spawn(aggregates::run(state.clone(), config.aggregate_interval));
+    if config.retention_days > 0 {
+        tokio::spawn(retention::run(state.clone(), config.retention_days, config.retention_interval));
+    }
+
+    let datasets = datasets::Datasets::start(&state);
+    let concurrency = ConcurrencyLimit::new(config.max_concurrent_requests, config.request_queue_size, config.request_queue_timeout);
 
     // build our application with a route
-    let app = Router::new()
-        // `GET /` goes to `root`
-        .route("/weight/measurement/:id", get(get_weight_measurement_id))
-        // `POST /users` goes to `create_user`
-        .route("/weight/measurement", post(create_weight_measurement))
-        .layer(cors);
+    let app = routes::router()
+        .layer(middleware::from_fn(move |req, next| datasets::dispatch(datasets.clone(), req, next)))
+        .layer(middleware::from_fn(envelope::wrap))
+        // outside the envelope, which serializes the body again
+        .layer(middleware::from_fn({
+            let fixed_point = config.fixed_point_numbers;
+            move |req, next| rounding::round(fixed_point, req, next)
+        }))
+        .layer(CatchPanicLayer::custom(panic_response))
+        .layer(middleware::from_fn(move |req, next| concurrency::limit(concurrency.clone(), req, next)))
+        .layer(cors)
+        .layer(middleware::from_fn(preflight_no_content))
+        .layer(middleware::from_fn({
+            let in_flight = in_flight.clone();
+            move |req, next| track_in_flight(in_flight.clone(), req, next)
+        }))
+        // just inside the span, so the bodies logged are the ones the client sends and gets
+        .layer(middleware::from_fn({
+            let enabled = config.log_bodies;
+            move |req, next| body_log::log(enabled, req, next)
+        }))
+        .layer(middleware::from_fn(request_span))
+        .layer(middleware::from_fn({
+            let send = config.send_server_header;
+            move |req, next| server_header(send, req, next)
+        }))
+        .layer(middleware::from_fn(response_time))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .with_state(state);
 
-    env::set_var("mongoDb.connectionString", "mongodb://127.0.0.1:27017/?directConnection=true&serverSelectionTimeoutMS=2000");
-    
-    tracing::info!("listening on {}", "127.0.0.1:3000");
-    axum::Server::bind(&"127.0.0.1:3000".parse().unwrap())
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+    let tls = match (&config.tls_cert_path, &config.tls_key_path) {
+        (Some(cert), Some(key)) => Some(RustlsConfig::from_pem_file(cert, key).await
+            .unwrap_or_else(|e| panic!("Error loading TLS certificate {} and key {}: {}", cert, key, e))),
+        (None, None) => None,
+        _ => panic!("TLS_CERT_PATH and TLS_KEY_PATH must be set together")
+    };
+
+    // fires once the shutdown signal arrives, so the drain deadline can start counting
+    let (signalled_tx, signalled_rx) = tokio::sync::oneshot::channel();
+    let handle = Handle::new();
+    tokio::spawn({
+        let handle = handle.clone();
+        let shutdown_timeout = config.shutdown_timeout;
+        async move {
+            shutdown_signal().await;
+            tracing::info!("shutting down, waiting up to {:?} for in-flight requests", shutdown_timeout);
+            // no timeout here, the deadline below reports what was still running
+            handle.graceful_shutdown(None);
+            let _ = signalled_tx.send(());
+        }
+    });
+
+    let addr: SocketAddr = "127.0.0.1:3000".parse().unwrap();
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let server = async {
+        match tls {
+            Some(tls) => axum_server::bind_rustls(addr, tls).handle(handle).serve(app.into_make_service()).await,
+            None => axum_server::bind(addr).handle(handle).serve(app.into_make_service()).await
+        }
+    };
+
+    tracing::info!("listening on {}://{}", scheme, addr);
+    tokio::select! {
+        result = server => result.unwrap(),
+        _ = async {
+            if signalled_rx.await.is_err() {
+                std::future::pending::<()>().await;
+            }
+            tokio::time::sleep(config.shutdown_timeout).await;
+        } => {
+            tracing::warn!("shutdown timeout reached with {} requests still in flight, exiting", in_flight.load(Ordering::SeqCst));
+            std::process::exit(1);
+        }
+    }
 }
 
-async fn get_collection<T>(database: &str, collection: &str) -> mongodb::error::Result<Collection<T>> {
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("failed to listen for ctrl-c");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {}
+    }
+}
+
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// counts requests currently being handled, reported if shutdown has to cut them off;
+// the guard rides along with the body so streamed exports count until fully sent
+async fn track_in_flight<B>(in_flight: Arc<AtomicUsize>, req: axum::http::Request<B>, next: Next<B>) -> Response {
+    in_flight.fetch_add(1, Ordering::SeqCst);
+    let guard = InFlightGuard(in_flight);
+    let response = next.run(req).await;
+    response.map(move |body| axum::body::boxed(body.map_data(move |data| {
+        let _ = &guard;
+        data
+    })))
+}
+
+// every log line of a request carries its id, method and path
+async fn request_span<B>(req: axum::http::Request<B>, next: Next<B>) -> Response {
+    let request_id = req.headers().get("x-request-id").and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+    let span = tracing::info_span!("request", id = %request_id, method = %req.method(), path = %req.uri().path());
+    next.run(req).instrument(span).await
+}
+
+// a panicking handler gets a 500 in the usual error format instead of a dropped connection;
+// this runs inside the request span, so the log line says which request it was
+fn panic_response(panic: Box<dyn std::any::Any + Send + 'static>) -> Response {
+    let message = panic.downcast_ref::<&str>().map(|m| m.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    AppError::Internal(format!("handler panicked: {}", message)).into_response()
+}
+
+// explicit headers rather than `Any`, which browsers won't honour for credentialed requests;
+// credentials are only allowed once the origins are restricted
+fn cors_layer(origins: &[String]) -> CorsLayer {
+    let cors = CorsLayer::new()
+        .allow_methods([Method::GET, Method::HEAD, Method::POST, Method::PUT, Method::PATCH, Method::DELETE, Method::OPTIONS])
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            HeaderName::from_static("idempotency-key"),
+            HeaderName::from_static("x-request-id")
+        ])
+        .expose_headers([HeaderName::from_static("x-total-count"), HeaderName::from_static("x-response-time")]);
+    let origins: Vec<HeaderValue> = origins.iter()
+        .filter_map(|origin| match HeaderValue::from_str(origin) {
+            Ok(v) => Some(v),
+            Err(_) => {
+                tracing::warn!("Ignoring invalid CORS origin {}", origin);
+                None
+            }
+        })
+        .collect();
+    if origins.is_empty() {
+        cors.allow_origin(Any)
+    } else {
+        cors.allow_origin(origins).allow_credentials(true)
+    }
+}
+
+// the CORS layer answers every `OPTIONS` itself with an empty 200, preflights get a 204 instead
+async fn preflight_no_content<B>(req: axum::http::Request<B>, next: Next<B>) -> Response {
+    let preflight = req.method() == Method::OPTIONS;
+    let mut response = next.run(req).await;
+    if preflight && response.status() == StatusCode::OK {
+        *response.status_mut() = StatusCode::NO_CONTENT;
+    }
+    response
+}
+
+const SERVER_HEADER: &str = concat!("rust-fabrust-back-end/", env!("CARGO_PKG_VERSION"));
+
+// identifies the service behind proxies unless `SEND_SERVER_HEADER=false`; a `Server` header
+// a handler already set is kept
+async fn server_header<B>(send: bool, req: axum::http::Request<B>, next: Next<B>) -> Response {
+    let mut response = next.run(req).await;
+    if send {
+        response.headers_mut().entry(header::SERVER).or_insert(HeaderValue::from_static(SERVER_HEADER));
+    }
+    response
+}
+
+// `X-Response-Time` in milliseconds, from just inside the request id layers until the response
+// head is ready; streamed bodies carry on after it
+async fn response_time<B>(req: axum::http::Request<B>, next: Next<B>) -> Response {
+    let started = Instant::now();
+    let mut response = next.run(req).await;
+    let elapsed = format!("{:.3}ms", started.elapsed().as_secs_f64() * 1_000_f64);
+    if let Ok(value) = HeaderValue::from_str(&elapsed) {
+        response.headers_mut().insert(HeaderName::from_static("x-response-time"), value);
+    }
+    response
+}
+
+// one client, and so one connection pool and one set of monitors, for every collection
+async fn get_database(database: &str) -> mongodb::error::Result<Database> {
     let mongodb_conn_string = read_env_var("mongoDb.connectionString", "localhost:4666");
 
     tracing::info!("DB on {}", mongodb_conn_string);
@@ -61,10 +402,13 @@ async fn get_collection<T>(database: &str, collection: &str) -> mongodb::error::
     client_options.server_api = Some(server_api);
     // Create a new client and connect to the server
     let client = Client::with_options(client_options)?;
-    // Get the database and colletion handles
-    let collection = client.database(database).collection::<T>(collection);
+    Ok(client.database(database))
+}
 
-    Ok(collection)
+// every write through the handle waits for `write_concern`, reads don't use it
+fn get_collection<T>(database: &Database, collection: &str, write_concern: &WriteConcern) -> Collection<T> {
+    let options = CollectionOptions::builder().write_concern(write_concern.clone()).build();
+    database.collection_with_options::<T>(collection, options)
 }
 
 fn read_env_var(env_name: &str, default: &str) -> String {
@@ -74,63 +418,655 @@ fn read_env_var(env_name: &str, default: &str) -> String {
     }
 }
 
+fn parse_object_id(id: &str) -> Result<bson::oid::ObjectId, AppError> {
+    bson::oid::ObjectId::parse_str(id).map_err(|_| AppError::InvalidId(id.to_string()))
+}
+
+// first measurement in `date` order, 1 for the earliest and -1 for the latest
+async fn find_by_date(state: &AppState, order: i32) -> mongodb::error::Result<Option<WheightMeasurementEntity>> {
+    let sort = doc! { "date": order };
+    let options = FindOneOptions::builder().sort(sort.clone()).build();
+    state.timed("find_one", &sort, state.collection.find_one(None, state.bounded(options))).await
+}
+
 // basic handler that responds with a static string
-async fn get_weight_measurement_id(Path(id): Path<String>) -> impl IntoResponse {
-    let collection = match get_collection::<WheightMeasurementEntity>("fabdev", "Wheights").await {
-        Ok(c) => c,
-        Err(e) => panic!("Error getting collection: {}", e)
+#[derive(Deserialize)]
+struct SinceQuery {
+    since: Option<DateTime<Utc>>,
+    // also report the diffs as measured, before the noise thresholds zero the small ones
+    #[serde(default)]
+    raw_diffs: bool
+}
+
+// the measurement to compare against for `?since=`: the last one at or before `since`,
+// or the first one after it when the history starts later
+async fn find_since_reference(state: &AppState, since: DateTime<Utc>) -> mongodb::error::Result<Option<WheightMeasurementEntity>> {
+    let since = BsonDateTime::from_chrono(since);
+    let before = FindOneOptions::builder().sort(doc! { "date": -1 }).build();
+    let filter = doc! { "date": { "$lte": since } };
+    if let Some(r) = state.timed("find_one", &filter, state.collection.find_one(filter.clone(), state.bounded(before))).await? {
+        return Ok(Some(r));
+    }
+    let after = FindOneOptions::builder().sort(doc! { "date": 1 }).build();
+    let filter = doc! { "date": { "$gt": since } };
+    state.timed("find_one", &filter, state.collection.find_one(filter.clone(), state.bounded(after))).await
+}
+
+async fn with_since(state: &AppState, mut output: WheightMeasurementOutput, since: Option<DateTime<Utc>>) -> Result<WheightMeasurementOutput, AppError> {
+    if let Some(since) = since {
+        let reference = find_since_reference(state, since).await?
+            .map(WheightMeasurementOutput::from_entity);
+        output.since = Some(SinceDiffs::between(&output, reference.as_ref()));
+    }
+    Ok(output)
+}
+
+async fn get_weight_measurement_id(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<SinceQuery>,
+    Negotiated(format): Negotiated,
+    AcceptLanguage(language): AcceptLanguage,
+    OutputUnit(unit): OutputUnit,
+) -> Result<Response, AppError> {
+    let oid = parse_object_id(&id)?;
+    tracing::info!("Filter: {}", doc! { "_id": oid }.log_summary());
+    let result = state.measurements.find_by_id(&state, oid).await?;
+
+    match result {
+        Some(r) => {
+            let validators = validator_headers(r.version);
+            let date = r.date;
+            let mut output = with_since(&state, WheightMeasurementOutput::from_entity(r), query.since).await?;
+            let thresholds = state.noise_thresholds().await?;
+            let previous = find_adjacent(&state, date, "$lt", -1).await?.map(WheightMeasurementOutput::from_entity);
+            let next = find_adjacent(&state, date, "$gt", 1).await?.map(WheightMeasurementOutput::from_entity);
+            if let Some(previous) = &previous {
+                output.set_diffs(previous, &thresholds, query.raw_diffs);
+            }
+            let weights: Vec<f32> = previous.iter().chain([&output]).chain(next.iter()).map(|m| m.wheight_kg).collect();
+            let outlier = quality::outlier_flags(&weights)[usize::from(previous.is_some())];
+            output = output.with_outlier(outlier);
+            let next_diff = next.map(|next| MeasurementDiff::between(&output, &next).suppress_noise(&thresholds).in_unit(unit));
+            let birth_date = state.profile().await?.birth_date;
+            let detail = WheightMeasurementDetailOutput { measurement: output.with_age(birth_date).localized(language).in_unit(unit), next_diff };
+            Ok((validators, format.render(StatusCode::OK, "measurement", &detail)?).into_response())
+        },
+        None => Err(AppError::NotFound)
+    }
+}
+
+// same lookup as the GET, but only the validators are fetched and no body is sent
+async fn head_weight_measurement_id(State(state): State<AppState>, Path(id): Path<String>) -> Result<impl IntoResponse, AppError> {
+    let filter = doc! { "_id": parse_object_id(&id)? };
+    let options = FindOneOptions::builder().projection(WheightMeasurementHeadEntity::projection()).build();
+    let result = state.timed("find_one", &filter, state.collection.clone_with_type::<WheightMeasurementHeadEntity>().find_one(filter.clone(), state.bounded(options))).await?;
+
+    match result {
+        Some(r) => Ok((StatusCode::OK, validator_headers(r.version))),
+        None => Err(AppError::NotFound)
+    }
+}
+
+// `ETag` from the version, which is what `If-Match` expects back. There is no `Last-Modified`
+// or 304: the body also holds the diffs to the neighbouring measurements and depends on the
+// profile and the language, none of which the document's `updated_at` tracks
+fn validator_headers(version: u32) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ETAG, HeaderValue::from_str(&format!("\"{}\"", version)).unwrap());
+    headers.insert(header::VARY, HeaderValue::from_static("Accept, Accept-Language"));
+    headers
+}
+
+// most recent measurement by `date`, served from the in-memory cache when possible
+async fn latest_measurement(state: &AppState) -> Result<Option<WheightMeasurementOutput>, AppError> {
+    if let Some(latest) = state.cached_latest().await {
+        return Ok(Some(latest));
+    }
+    match find_by_date(state, -1).await? {
+        Some(r) => {
+            let latest = WheightMeasurementOutput::from_entity(r);
+            state.cache_latest(latest.clone()).await;
+            Ok(Some(latest))
+        },
+        None => Ok(None)
+    }
+}
+
+async fn get_latest_weight_measurement(State(state): State<AppState>, Query(query): Query<SinceQuery>, AcceptLanguage(language): AcceptLanguage, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let Some(latest) = latest_measurement(&state).await? else {
+        return Err(AppError::NotFound);
     };
 
-    let filter = doc! { "_id": bson::oid::ObjectId::parse_str(id).unwrap() };
-    tracing::info!("Filter: {:?}", filter);
-    let result = match collection.find_one(filter, None).await {
-        Ok(r) => r,
-        Err(e) => panic!("Error finding document: {}", e)
+    let profile = state.profile().await?;
+    let target_status = profile.target_band.map(|band| band.status(latest.wheight_kg).in_unit(unit));
+    let measurement = with_since(&state, latest, query.since).await?.with_age(profile.birth_date).localized(language).in_unit(unit);
+    Ok((StatusCode::OK, Json(WheightMeasurementLatestOutput { measurement, target_status })))
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum ListView {
+    // just what a table/chart row needs, projected in the database
+    #[default]
+    Summary,
+    Full
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    // strings so out-of-range numbers can be clamped instead of failing to deserialize
+    page: Option<String>,
+    limit: Option<String>,
+    sort: Option<String>,
+    order: Option<String>,
+    #[serde(default)]
+    view: ListView,
+    // diffs need the full documents, so this implies `view=full`
+    #[serde(default)]
+    with_diffs: bool,
+    // only measurements with (true) or without (false) a `photo_url`
+    has_photo: Option<bool>,
+    #[serde(default)]
+    favorites_only: bool,
+    // only measurements whose `imc` falls in this WHO class
+    bmi_category: Option<String>,
+    // with `with_diffs`, also the diffs as measured before the noise thresholds apply
+    #[serde(default)]
+    raw_diffs: bool
+}
+
+// the list's optional filters, an empty document when none is given
+fn list_filter(query: &ListQuery) -> Result<bson::Document, AppError> {
+    let mut filter = doc! {};
+    // older documents have no `photo_url` at all, others an explicit null
+    match query.has_photo {
+        Some(true) => { filter.insert("photo_url", doc! { "$type": "string" }); },
+        Some(false) => { filter.insert("photo_url", doc! { "$not": { "$type": "string" } }); },
+        None => {}
+    }
+    if query.favorites_only {
+        filter.insert("is_favorite", true);
+    }
+    if let Some(category) = query.bmi_category.as_deref() {
+        filter.insert("imc", ImcCategory::parse(category)?.imc_filter());
+    }
+    Ok(filter)
+}
+
+// fields the list can be ordered by, anything else is rejected so clients can't sort on arbitrary paths
+const SORTABLE_FIELDS: [&str; 3] = ["date", "wheight_kg", "fat_percentage"];
+
+fn sort_document(sort: Option<&str>, order: Option<&str>) -> Result<bson::Document, AppError> {
+    let field = sort.unwrap_or("date");
+    if !SORTABLE_FIELDS.contains(&field) {
+        return Err(AppError::BadRequest(format!("Cannot sort by '{}', expected one of {}", field, SORTABLE_FIELDS.join(", "))));
+    }
+    let direction = match order.unwrap_or("desc") {
+        "asc" => 1,
+        "desc" => -1,
+        other => return Err(AppError::BadRequest(format!("Unknown order '{}', expected asc or desc", other)))
     };
+    Ok(doc! { field: direction })
+}
 
-    match result {
-        Some(r) => (StatusCode::OK, Json(WheightMeasurementOutput::from_entity(r))).into_response(),
-        None => (StatusCode::NOT_FOUND, Json(ErrorResponse { message: "Not Found" })).into_response()   
+// integer query value clamped to `[min, max]`, however far outside it is; only non-numbers are rejected
+fn clamped_param(name: &str, value: Option<&str>, default: i64, min: i64, max: i64) -> Result<i64, AppError> {
+    let Some(value) = value.map(str::trim) else {
+        return Ok(default.clamp(min, max));
+    };
+    let digits = value.strip_prefix(['+', '-']).unwrap_or(value);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(AppError::BadRequest(format!("{} must be an integer, got '{}'", name, value)));
+    }
+    let parsed = value.parse::<i64>().unwrap_or(if value.starts_with('-') { i64::MIN } else { i64::MAX });
+    Ok(parsed.clamp(min, max))
+}
+
+// `total` again as a header, where admin UIs like react-admin look for it
+fn total_count_header(total: u64) -> [(HeaderName, HeaderValue); 1] {
+    [(HeaderName::from_static("x-total-count"), HeaderValue::from(total))]
+}
+
+// page of measurements, newest first unless `sort`/`order` say otherwise
+async fn list_weight_measurements(State(state): State<AppState>, Query(query): Query<ListQuery>, Negotiated(format): Negotiated, AcceptLanguage(language): AcceptLanguage, OutputUnit(unit): OutputUnit) -> Result<Response, AppError> {
+    // a page this deep is empty anyway, the cap only keeps `skip` from overflowing
+    let page = clamped_param("page", query.page.as_deref(), 0, 0, i64::MAX / state.config.max_page_size)? as u64;
+    // asking for more than the max isn't an error, the effective limit is reported back
+    let limit = clamped_param("limit", query.limit.as_deref(), state.config.default_page_size, 1, state.config.max_page_size)?;
+    let sort = sort_document(query.sort.as_deref(), query.order.as_deref())?;
+    if query.with_diffs && sort != doc! { "date": -1 } {
+        return Err(AppError::BadRequest("with_diffs is only available for the default newest-first order".to_string()));
+    }
+    let filter = list_filter(&query)?;
+    // a filtered page's diffs would be against the previous match, not the previous measurement
+    if query.with_diffs && !filter.is_empty() {
+        return Err(AppError::BadRequest("with_diffs can't be combined with filters".to_string()));
+    }
+    let total = state.timed("count_documents", &filter, state.collection.count_documents(filter.clone(), state.bounded(CountOptions::default()))).await?;
+
+    if query.view == ListView::Summary && !query.with_diffs {
+        let options = FindOptions::builder()
+            .sort(sort.clone())
+            .skip(page * limit as u64)
+            .limit(limit)
+            .projection(WheightMeasurementSummaryEntity::projection())
+            .build();
+        let mut cursor = state.timed("find", &sort, state.collection.clone_with_type::<WheightMeasurementSummaryEntity>().find(filter.clone(), state.bounded(options))).await?;
+        let mut items = Vec::new();
+        while cursor.advance().await? {
+            items.push(WheightMeasurementSummaryOutput::from_entity(cursor.deserialize_current()?).in_unit(unit));
+        }
+        let body = format.render(StatusCode::OK, "measurements", &WheightMeasurementListOutput { items, page, limit, total })?;
+        return Ok((total_count_header(total), Extension(Pagination { page, limit, total }), body).into_response());
     }
+
+    // with diffs we also need the record right before the page, to diff its last row against
+    let fetch = if query.with_diffs { limit + 1 } else { limit };
+    let options = FindOptions::builder()
+        .sort(sort.clone())
+        .skip(page * limit as u64)
+        .limit(fetch)
+        .build();
+    let filter_is_empty = filter.is_empty();
+    let mut cursor = state.timed("find", &sort, state.collection.find(filter, state.bounded(options))).await?;
+    let mut items = Vec::new();
+    while cursor.advance().await? {
+        items.push(WheightMeasurementOutput::from_entity(cursor.deserialize_current()?));
+    }
+
+    if query.with_diffs {
+        let thresholds = state.noise_thresholds().await?;
+        // sorted newest first, so each row's predecessor is the one after it
+        for i in 0..items.len().saturating_sub(1) {
+            let (current, previous) = items.split_at_mut(i + 1);
+            current[i].set_diffs(&previous[0], &thresholds, query.raw_diffs);
+        }
+        items.truncate(limit as usize);
+    }
+    // only a page in date order without filters holds each row's neighbours
+    if filter_is_empty && (sort == doc! { "date": -1 } || sort == doc! { "date": 1 }) {
+        let weights: Vec<f32> = items.iter().map(|m| m.wheight_kg).collect();
+        items = items.into_iter().zip(quality::outlier_flags(&weights)).map(|(m, outlier)| m.with_outlier(outlier)).collect();
+    }
+    let birth_date = state.profile().await?.birth_date;
+    let items: Vec<WheightMeasurementOutput> = items.into_iter().map(|m| m.with_age(birth_date).localized(language).in_unit(unit)).collect();
+
+    let body = format.render(StatusCode::OK, "measurements", &WheightMeasurementListOutput { items, page, limit, total })?;
+    Ok((total_count_header(total), Extension(Pagination { page, limit, total }), body).into_response())
+}
+
+// the earliest measurement, the starting point of the journey
+async fn get_first_weight_measurement(State(state): State<AppState>, AcceptLanguage(language): AcceptLanguage, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let birth_date = state.profile().await?.birth_date;
+    match find_by_date(&state, 1).await? {
+        Some(r) => Ok((StatusCode::OK, Json(WheightMeasurementOutput::from_entity(r).with_age(birth_date).localized(language).in_unit(unit)))),
+        None => Err(AppError::NotFound)
+    }
+}
+
+#[derive(Deserialize)]
+struct WheightMeasurementDateEntity {
+    _id: bson::oid::ObjectId,
+    date: mongodb::bson::DateTime
+}
+
+#[derive(Serialize)]
+struct DateRangeOutput {
+    earliest: Option<DateTime<Utc>>,
+    latest: Option<DateTime<Utc>>
+}
+
+// date of the first measurement in `date` order, only the date is fetched
+async fn find_date(state: &AppState, order: i32) -> mongodb::error::Result<Option<DateTime<Utc>>> {
+    let options = FindOneOptions::builder().sort(doc! { "date": order }).projection(doc! { "date": 1 }).build();
+    let result = state.timed("find_one", "date bound", state.collection.clone_with_type::<WheightMeasurementDateEntity>().find_one(None, state.bounded(options))).await?;
+    Ok(result.map(|r| r.date.to_chrono()))
+}
+
+// bounds of the history for date pickers, both null when there's nothing yet
+async fn get_date_range(State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
+    Ok((StatusCode::OK, Json(DateRangeOutput {
+        earliest: find_date(&state, 1).await?,
+        latest: find_date(&state, -1).await?
+    })))
 }
 
 async fn create_weight_measurement(
-    // this argument tells axum to parse the request body
-    // as JSON into a `WheightMeasurementInput` type
-    Json(payload): Json<WheightMeasurementInput>,
-) -> impl IntoResponse {
+    State(state): State<AppState>,
+    // this argument tells axum to parse the request body as JSON into a
+    // `WheightMeasurementInput` type and run its validation
+    ValidatedJson(mut payload): ValidatedJson<WheightMeasurementInput>,
+) -> Result<impl IntoResponse, AppError> {
     // insert your application logic here
-    let measurement = WheightMeasurementEntity {
-        _id: bson::oid::ObjectId::default(),
-        date: BsonDateTime::from_chrono(payload.date),
-        wheight_kg: payload.wheight_kg,
-        imc: payload.imc,
-        fat_percentage: payload.fat_percentage,
-        water_percentage: payload.water_percentage,
-        protein_percentage: payload.protein_percentage,
-        metabolism_kcal: payload.metabolism_kcal,
-        visceral_fat_index: payload.visceral_fat_index,
-        muscle_kg: payload.muscle_kg,
-        bone_kg: payload.bone_kg,
-        metabolic_age: payload.metabolic_age,
-        fat_kg: payload.wheight_kg * (payload.fat_percentage / 100_f32),
-        muscle_percentage: 100_f32 * (payload.muscle_kg / payload.wheight_kg)
-    };
-
-    let collection = match get_collection::<WheightMeasurementEntity>("fabdev", "Wheights").await {
-        Ok(c) => c,
-        Err(e) => panic!("Error getting collection: {}", e)
-    };
-
-    let result = match collection.insert_one(measurement, None).await{
-        Ok(r) => r,
-        Err(e) => panic!("Error inserting document: {}", e)
-    };
-
-    let response = WheightMeasurementIdResponse { id: result.inserted_id.to_string() };
+    let warnings = state.reconcile_imc(&mut payload).await?.into_iter().collect();
+    let measurement = insert_measurement(&state, payload).await?;
+
+    let response = WheightMeasurementIdResponse { id: measurement._id.to_hex(), warnings };
     // this will be converted into a JSON response
     // with a status code of `201 Created`
-    (StatusCode::CREATED, Json(response))
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+// 409 when a measurement other than `except` is within `DEDUP_WINDOW_MINUTES` of `date`, or
+// at exactly `date` when the window is 0
+async fn reject_duplicate(state: &AppState, date: DateTime<Utc>, except: Option<bson::oid::ObjectId>) -> Result<(), AppError> {
+    let minutes = state.config.dedup_window_minutes;
+    let window = chrono::Duration::minutes(minutes);
+    let (from, to) = (date.checked_sub_signed(window).unwrap_or(date), date.checked_add_signed(window).unwrap_or(date));
+    let (from, to) = (BsonDateTime::from_chrono(from), BsonDateTime::from_chrono(to));
+    match state.measurements.find_between(state, from, to, except).await? {
+        Some(existing) => Err(AppError::Conflict(format!(
+            "Measurement {} at {} is within {} minutes of this one", existing._id.to_hex(), existing.date.to_chrono(), minutes
+        ))),
+        None => Ok(())
+    }
+}
+
+// stores a new measurement built from `input`, with its derived fields computed
+async fn insert_measurement(state: &AppState, input: WheightMeasurementInput) -> Result<WheightMeasurementEntity, AppError> {
+    let measurement = WheightMeasurementEntity::from_input(input);
+    if state.config.dedup_window_minutes > 0 {
+        // the check and the insert aren't atomic, two creates racing can still both get in
+        reject_duplicate(state, measurement.date.to_chrono(), None).await?;
+    }
+    state.measurements.insert(state, &measurement).await?;
+    state.invalidate_latest(&measurement._id.to_hex(), measurement.date.to_chrono()).await;
+    state.audit(vec![AuditEntity::new(AuditOperation::Create, None, Some(&measurement))]).await;
+    Ok(measurement)
+}
+
+#[derive(Deserialize)]
+struct DuplicateQuery {
+    // of the copy, now when absent
+    date: Option<DateTime<Utc>>
+}
+
+// a new measurement with the composition of `id` at a new date, for manual entries where little
+// changes from day to day; the notes and photo describe the original's day and aren't copied
+async fn duplicate_weight_measurement(State(state): State<AppState>, Path(id): Path<String>, Query(query): Query<DuplicateQuery>) -> Result<impl IntoResponse, AppError> {
+    let Some(source) = state.measurements.find_by_id(&state, parse_object_id(&id)?).await? else {
+        return Err(AppError::NotFound);
+    };
+    let input = WheightMeasurementInput {
+        date: query.date.unwrap_or_else(Utc::now),
+        wheight_kg: source.wheight_kg,
+        imc: source.imc,
+        fat_percentage: source.fat_percentage,
+        water_percentage: source.water_percentage,
+        protein_percentage: source.protein_percentage,
+        metabolism_kcal: source.metabolism_kcal,
+        visceral_fat_index: source.visceral_fat_index,
+        muscle_kg: source.muscle_kg,
+        bone_kg: source.bone_kg,
+        metabolic_age: source.metabolic_age,
+        source: source.source,
+        notes: None,
+        tags: source.tags,
+        photo_url: None
+    };
+    // the original may predate a rule the copy has to follow
+    input.validate()?;
+    let measurement = insert_measurement(&state, input).await?;
+    Ok((StatusCode::CREATED, Json(WheightMeasurementOutput::from_entity(measurement))))
+}
+
+// the version the client last saw, from an `If-Match: "<version>"` header
+fn expected_version(headers: &HeaderMap) -> Result<Option<u32>, AppError> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+    let version = value.to_str().ok()
+        .map(|v| v.trim().trim_start_matches("W/").trim_matches('"'))
+        .and_then(|v| v.parse::<u32>().ok());
+    match version {
+        Some(v) => Ok(Some(v)),
+        None => Err(AppError::BadRequest("If-Match must be a measurement version".to_string()))
+    }
+}
+
+// full replacement of a measurement, guarded by `If-Match` against lost updates
+async fn update_weight_measurement(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    ValidatedJson(payload): ValidatedJson<WheightMeasurementInput>,
+) -> Result<impl IntoResponse, AppError> {
+    let oid = parse_object_id(&id)?;
+    let expected = expected_version(&headers)?;
+
+    let measurement = WheightMeasurementEntity::from_input(payload);
+    let date = measurement.date.to_chrono();
+    let mut set = bson::to_document(&measurement)?;
+    set.remove("_id");
+    set.remove("version");
+    set.remove("is_favorite");
+    set.remove("is_locked");
+
+    let Some((before, after)) = state.measurements.update(&state, oid, expected, set).await? else {
+        return Err(match state.measurements.find_by_id(&state, oid).await? {
+            Some(current) if current.is_locked => lock::locked(),
+            Some(_) => AppError::Conflict("Measurement was modified by someone else".to_string()),
+            None => AppError::NotFound
+        });
+    };
+    state.invalidate_latest(&id, date).await;
+    // a back-dated edit may have moved the measurement off its old day
+    aggregates::refresh_days(&state, &[before.date.to_chrono().date_naive(), date.date_naive()]).await;
+    state.audit(vec![AuditEntity::new(AuditOperation::Update, Some(&before), Some(&after))]).await;
+    Ok((StatusCode::OK, Json(WheightMeasurementOutput::from_entity(after))))
+}
+
+// everything derived from a measurement that has to go with it; each step is idempotent, so a
+// DELETE that failed halfway can simply be repeated
+async fn clean_up_deleted(state: &AppState, measurement: &WheightMeasurementEntity) {
+    let date = measurement.date.to_chrono();
+    state.invalidate_latest(&measurement._id.to_hex(), date).await;
+    // the facets list its tags
+    *state.facets.write().await = None;
+    // the aggregation run only picks up dates through `updated_at`, which a deleted document no
+    // longer has, so the day is recomputed here
+    aggregates::refresh_days(state, &[date.date_naive()]).await;
+}
+
+async fn delete_weight_measurement(State(state): State<AppState>, Path(id): Path<String>) -> Result<impl IntoResponse, AppError> {
+    let oid = parse_object_id(&id)?;
+    let Some(before) = state.measurements.delete(&state, oid).await? else {
+        if state.measurements.find_by_id(&state, oid).await?.is_some_and(|m| m.is_locked) {
+            return Err(lock::locked());
+        }
+        // already deleted by an earlier attempt, whose cleanup may not have finished
+        return match state.measurements.find_deletion(&state, oid).await? {
+            Some(deletion) => {
+                if let Some(before) = deletion.before() {
+                    tracing::info!("Measurement {} was already deleted, repeating its cleanup", id);
+                    clean_up_deleted(&state, before).await;
+                }
+                Ok(StatusCode::NO_CONTENT)
+            },
+            None => Err(AppError::NotFound)
+        };
+    };
+    state.audit(vec![AuditEntity::new(AuditOperation::Delete, Some(&before), None)]).await;
+    clean_up_deleted(&state, &before).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize)]
+struct NeighborOutput {
+    id: String,
+    date: DateTime<Utc>
+}
+
+#[derive(Serialize)]
+struct NeighborsOutput {
+    previous: Option<NeighborOutput>,
+    next: Option<NeighborOutput>
+}
+
+// nearest full measurement strictly before (`$lt`, -1) or after (`$gt`, 1) `date`
+async fn find_adjacent(state: &AppState, date: BsonDateTime, operator: &str, order: i32) -> mongodb::error::Result<Option<WheightMeasurementEntity>> {
+    let filter = doc! { "date": { operator: date } };
+    let options = FindOneOptions::builder().sort(doc! { "date": order }).build();
+    state.timed("find_one", &filter, state.collection.find_one(filter.clone(), state.bounded(options))).await
+}
+
+async fn find_neighbor(state: &AppState, date: BsonDateTime, operator: &str, order: i32) -> mongodb::error::Result<Option<NeighborOutput>> {
+    let filter = doc! { "date": { operator: date } };
+    let options = FindOneOptions::builder().sort(doc! { "date": order }).projection(doc! { "date": 1 }).build();
+    let result = state.timed("find_one", &filter, state.collection.clone_with_type::<WheightMeasurementDateEntity>().find_one(filter.clone(), state.bounded(options))).await?;
+    Ok(result.map(|r| NeighborOutput { id: r._id.to_hex(), date: r.date.to_chrono() }))
+}
+
+// ids of the chronologically adjacent measurements, for prev/next navigation
+async fn get_neighbors(State(state): State<AppState>, Path(id): Path<String>) -> Result<impl IntoResponse, AppError> {
+    let filter = doc! { "_id": parse_object_id(&id)? };
+    let options = FindOneOptions::builder().projection(doc! { "date": 1 }).build();
+    let Some(current) = state.timed("find_one", &filter, state.collection.clone_with_type::<WheightMeasurementDateEntity>().find_one(filter.clone(), state.bounded(options))).await? else {
+        return Err(AppError::NotFound);
+    };
+
+    Ok((StatusCode::OK, Json(NeighborsOutput {
+        previous: find_neighbor(&state, current.date, "$lt", -1).await?,
+        next: find_neighbor(&state, current.date, "$gt", 1).await?
+    })))
+}
+
+const DEFAULT_AROUND: i64 = 3;
+const MAX_AROUND: i64 = 25;
+
+#[derive(Deserialize)]
+struct AroundQuery {
+    date: DateTime<Utc>,
+    n: Option<i64>
+}
+
+// up to `n` measurements on or before `date` and up to `n` after it, oldest first
+async fn get_around(State(state): State<AppState>, Query(query): Query<AroundQuery>, AcceptLanguage(language): AcceptLanguage, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let n = query.n.unwrap_or(DEFAULT_AROUND);
+    if !(1..=MAX_AROUND).contains(&n) {
+        return Err(AppError::BadRequest(format!("n must be between 1 and {}", MAX_AROUND)));
+    }
+
+    let date = BsonDateTime::from_chrono(query.date);
+    let mut items = Vec::new();
+    for (operator, order) in [("$lte", -1), ("$gt", 1)] {
+        let filter = doc! { "date": { operator: date } };
+        let options = FindOptions::builder().sort(doc! { "date": order }).limit(n).build();
+        let mut cursor = state.timed("find", &filter, state.collection.find(filter.clone(), state.bounded(options))).await?;
+        while cursor.advance().await? {
+            items.push(WheightMeasurementOutput::from_entity(cursor.deserialize_current()?));
+        }
+    }
+    items.sort_by_key(|m| m.date);
+
+    let birth_date = state.profile().await?.birth_date;
+    let items: Vec<WheightMeasurementOutput> = items.into_iter().map(|m| m.with_age(birth_date).localized(language).in_unit(unit)).collect();
+    Ok((StatusCode::OK, Json(items)))
+}
+
+const MAX_BY_IDS: usize = 100;
+
+#[derive(Deserialize)]
+struct ByIdsQuery {
+    // comma-separated
+    ids: String
+}
+
+#[derive(Serialize)]
+struct ByIdsOutput {
+    // in the order the ids were asked for, each id once
+    measurements: Vec<WheightMeasurementOutput>,
+    // asked for but not found, deleted ones included, so a client cache can drop them
+    missing: Vec<String>
+}
+
+// several measurements by id in one query, for refreshing a client cache
+async fn get_by_ids(State(state): State<AppState>, Query(query): Query<ByIdsQuery>, AcceptLanguage(language): AcceptLanguage, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let mut ids = Vec::new();
+    for id in query.ids.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        let oid = parse_object_id(id)?;
+        if !ids.contains(&oid) {
+            ids.push(oid);
+        }
+    }
+    if ids.is_empty() || ids.len() > MAX_BY_IDS {
+        return Err(AppError::BadRequest(format!("ids must name between 1 and {} measurements", MAX_BY_IDS)));
+    }
+
+    let filter = doc! { "_id": { "$in": &ids } };
+    let mut cursor = state.timed("find", &filter, state.collection.find(filter.clone(), state.bounded(FindOptions::default()))).await?;
+    let mut found = HashMap::with_capacity(ids.len());
+    while cursor.advance().await? {
+        let m = cursor.deserialize_current()?;
+        found.insert(m._id, m);
+    }
+
+    let birth_date = state.profile().await?.birth_date;
+    let (mut measurements, mut missing) = (Vec::with_capacity(found.len()), Vec::new());
+    for id in ids {
+        match found.remove(&id) {
+            Some(m) => measurements.push(WheightMeasurementOutput::from_entity(m).with_age(birth_date).localized(language).in_unit(unit)),
+            None => missing.push(id.to_hex())
+        }
+    }
+    Ok((StatusCode::OK, Json(ByIdsOutput { measurements, missing })))
+}
+
+#[derive(Deserialize)]
+struct DeviceOffsetQuery {
+    source: String,
+    // when absent, every measurement not taken by `source` is used as the reference
+    reference: Option<String>
+}
+
+// mean `wheight_kg` difference between `source` and the reference on days both measured
+async fn get_device_offset(State(state): State<AppState>, Query(query): Query<DeviceOffsetQuery>, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let filter = match &query.reference {
+        Some(reference) => doc! { "source": { "$in": [&query.source, reference] } },
+        None => doc! {}
+    };
+    let mut cursor = state.timed("find", &filter, state.collection.find(filter.clone(), state.bounded(FindOptions::default()))).await?;
+
+    // per day (sum, count) of the weights taken by each side
+    let mut source_days: BTreeMap<NaiveDate, (f32, u32)> = BTreeMap::new();
+    let mut reference_days: BTreeMap<NaiveDate, (f32, u32)> = BTreeMap::new();
+    while cursor.advance().await? {
+        let entity = cursor.deserialize_current()?;
+        let is_source = entity.source.as_deref() == Some(query.source.as_str());
+        let is_reference = match &query.reference {
+            Some(reference) => entity.source.as_deref() == Some(reference.as_str()),
+            None => !is_source
+        };
+        let days = if is_source {
+            &mut source_days
+        } else if is_reference {
+            &mut reference_days
+        } else {
+            continue;
+        };
+        let day = days.entry(entity.date.to_chrono().date_naive()).or_default();
+        day.0 += entity.wheight_kg;
+        day.1 += 1;
+    }
+
+    let offsets: Vec<f32> = source_days.iter()
+        .filter_map(|(date, source)| reference_days.get(date).map(|reference| {
+            source.0 / source.1 as f32 - reference.0 / reference.1 as f32
+        }))
+        .collect();
+    let mean_offset_kg = match offsets.len() {
+        0 => None,
+        n => Some(offsets.iter().sum::<f32>() / n as f32)
+    };
+
+    Ok((StatusCode::OK, Json(DeviceOffsetOutput {
+        source: query.source,
+        reference: query.reference,
+        mean_offset_kg: mean_offset_kg.map(|kg| unit.convert_kg(kg)),
+        paired_days: offsets.len(),
+        unit
+    })))
 }
 
 // the input to our `create_user` handler
@@ -146,20 +1082,104 @@ struct WheightMeasurementInput {
     visceral_fat_index: f32,
     muscle_kg: f32,
     bone_kg: f32,
-    metabolic_age: u8
+    metabolic_age: u8,
+    // the scale/device that took the measurement, e.g. "omron" or "gym"
+    source: Option<String>,
+    notes: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    // where a progress photo is hosted, only the reference is stored
+    photo_url: Option<String>
 }
 
 #[derive(Serialize)]
 struct WheightMeasurementIdResponse {
-    id: String
+    id: String,
+    // accepted but suspicious values, see `Warning`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<Warning>
+}
+
+#[derive(Serialize)]
+struct DeviceOffsetOutput {
+    source: String,
+    reference: Option<String>,
+    mean_offset_kg: Option<f32>,
+    paired_days: usize,
+    unit: Unit
 }
 
 #[derive(Serialize)]
-struct ErrorResponse<'a> {
-    message: &'a str
+struct WheightMeasurementListOutput<T> {
+    items: Vec<T>,
+    page: u64,
+    limit: i64,
+    total: u64
+}
+
+// the fields fetched for list rows, everything else stays in the database
+#[derive(Deserialize)]
+struct WheightMeasurementSummaryEntity {
+    _id: bson::oid::ObjectId,
+    date: mongodb::bson::DateTime,
+    wheight_kg: f32,
+    fat_percentage: f32,
+    #[serde(default)]
+    muscle_percentage: f32,
+    // only read to recompute a missing `muscle_percentage`
+    #[serde(default)]
+    muscle_kg: f32
 }
 
-#[derive(Serialize, Deserialize)]
+impl WheightMeasurementSummaryEntity {
+    pub fn projection() -> bson::Document {
+        doc! { "date": 1, "wheight_kg": 1, "fat_percentage": 1, "muscle_percentage": 1, "muscle_kg": 1 }
+    }
+}
+
+// just enough to answer a HEAD request
+#[derive(Deserialize)]
+struct WheightMeasurementHeadEntity {
+    #[serde(default)]
+    version: u32
+}
+
+impl WheightMeasurementHeadEntity {
+    pub fn projection() -> bson::Document {
+        doc! { "version": 1 }
+    }
+}
+
+#[derive(Serialize)]
+struct WheightMeasurementSummaryOutput {
+    id: String,
+    date: DateTime<Utc>,
+    wheight_kg: f32,
+    fat_percentage: f32,
+    muscle_percentage: f32,
+    unit: Unit
+}
+
+impl WheightMeasurementSummaryOutput {
+    pub fn from_entity(entity: WheightMeasurementSummaryEntity) -> Self {
+        WheightMeasurementSummaryOutput {
+            id: entity._id.to_string(),
+            date: entity.date.to_chrono(),
+            wheight_kg: entity.wheight_kg,
+            fat_percentage: entity.fat_percentage,
+            muscle_percentage: or_derived(entity.muscle_percentage, || derive_muscle_percentage(entity.muscle_kg, entity.wheight_kg)),
+            unit: Unit::Kg
+        }
+    }
+
+    pub fn in_unit(mut self, unit: Unit) -> Self {
+        self.wheight_kg = unit.convert_kg(self.wheight_kg);
+        self.unit = unit;
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 struct WheightMeasurementEntity {
     _id: bson::oid::ObjectId,
     date: mongodb::bson::DateTime,
@@ -173,17 +1193,39 @@ struct WheightMeasurementEntity {
     muscle_kg: f32,
     bone_kg: f32,
     metabolic_age: u8,
+    // derived on write, missing (0) on documents older than them
+    #[serde(default)]
     fat_kg: f32,
-    muscle_percentage: f32
+    #[serde(default)]
+    muscle_percentage: f32,
+    source: Option<String>,
+    notes: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    photo_url: Option<String>,
+    // only changed through the favorite endpoints, never by a write of the measurement itself
+    #[serde(default)]
+    is_favorite: bool,
+    // only changed through the lock endpoints; a locked measurement can't be updated or deleted
+    #[serde(default)]
+    is_locked: bool,
+    // bumped on every update, documents from before versioning read as 0
+    #[serde(default)]
+    version: u32,
+    // set on every write, missing on documents from before it was tracked
+    updated_at: Option<mongodb::bson::DateTime>
 }
 
 // the output to our `create_user` handler
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 struct WheightMeasurementOutput {
     id: String,
     date: DateTime<Utc>,
     wheight_kg: f32,
     imc: f32,
+    // stable key for client logic, null when `imc` is missing, and its label in the request's language
+    imc_category: Option<ImcCategory>,
+    category_label: Option<&'static str>,
     fat_percentage: f32,
     water_percentage: f32,
     protein_percentage: f32,
@@ -192,23 +1234,273 @@ struct WheightMeasurementOutput {
     muscle_kg: f32,
     bone_kg: f32,
     metabolic_age: u8,
+    // age on the measurement date, null until the profile has a `birth_date`
+    chronological_age: Option<u32>,
+    metabolic_age_delta: Option<i32>,
     fat_kg: f32,
     muscle_percentage: f32,
+    source: Option<String>,
+    notes: Option<String>,
+    tags: Vec<String>,
+    photo_url: Option<String>,
+    is_favorite: bool,
+    is_locked: bool,
+    version: u32,
+    updated_at: Option<DateTime<Utc>>,
+    // unit of every `*_kg` field, they are always computed in kg and converted last
+    unit: Unit,
     wheight_kg_diff: f32,
     fat_percentage_diff: f32,
     muscle_kg_diff: f32,
     bone_kg_diff: f32,
     fat_kg_diff: f32,
-    muscle_percentage_diff: f32
+    muscle_percentage_diff: f32,
+    // only when asked for with `?raw_diffs=true`, the `_diff`s before noise suppression
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_diffs: Option<MeasurementDiff>,
+    // confidence hint for weighting points in charts, `high` only where the neighbouring
+    // measurements were read (the list in date order and the single read)
+    quality: Quality,
+    // only present when the request asked for `?since=`
+    #[serde(flatten)]
+    since: Option<SinceDiffs>
+}
+
+// single-get output: the measurement, its `_diff` from the previous one, and how the next one
+// changed from it (null for the latest)
+#[derive(Serialize)]
+struct WheightMeasurementDetailOutput {
+    #[serde(flatten)]
+    measurement: WheightMeasurementOutput,
+    next_diff: Option<MeasurementDiff>
+}
+
+// latest output: the measurement and, once the profile has a `target_band`, where it sits in it
+#[derive(Serialize)]
+struct WheightMeasurementLatestOutput {
+    #[serde(flatten)]
+    measurement: WheightMeasurementOutput,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_status: Option<target::TargetStatus>
+}
+
+#[derive(Serialize, Clone)]
+struct MeasurementDiff {
+    wheight_kg: f32,
+    fat_percentage: f32,
+    muscle_kg: f32,
+    bone_kg: f32,
+    fat_kg: f32,
+    muscle_percentage: f32
+}
+
+impl MeasurementDiff {
+    fn between(from: &WheightMeasurementOutput, to: &WheightMeasurementOutput) -> Self {
+        MeasurementDiff {
+            wheight_kg: to.wheight_kg - from.wheight_kg,
+            fat_percentage: to.fat_percentage - from.fat_percentage,
+            muscle_kg: to.muscle_kg - from.muscle_kg,
+            bone_kg: to.bone_kg - from.bone_kg,
+            fat_kg: to.fat_kg - from.fat_kg,
+            muscle_percentage: to.muscle_percentage - from.muscle_percentage
+        }
+    }
+
+    // zeroes the changes smaller than their threshold, before any unit conversion as the
+    // thresholds are in kg
+    fn suppress_noise(self, thresholds: &NoiseThresholds) -> Self {
+        MeasurementDiff {
+            wheight_kg: noise::suppress(self.wheight_kg, thresholds.wheight_kg),
+            fat_percentage: noise::suppress(self.fat_percentage, thresholds.fat_percentage),
+            muscle_kg: noise::suppress(self.muscle_kg, thresholds.muscle_kg),
+            bone_kg: noise::suppress(self.bone_kg, thresholds.bone_kg),
+            fat_kg: noise::suppress(self.fat_kg, thresholds.fat_kg),
+            muscle_percentage: noise::suppress(self.muscle_percentage, thresholds.muscle_percentage)
+        }
+    }
+
+    fn in_unit(mut self, unit: Unit) -> Self {
+        self.wheight_kg = unit.convert_kg(self.wheight_kg);
+        self.muscle_kg = unit.convert_kg(self.muscle_kg);
+        self.bone_kg = unit.convert_kg(self.bone_kg);
+        self.fat_kg = unit.convert_kg(self.fat_kg);
+        self
+    }
+}
+
+// change relative to the `?since=` reference, all null when there is no reference
+#[derive(Serialize, Clone)]
+struct SinceDiffs {
+    since_reference_date: Option<DateTime<Utc>>,
+    wheight_kg_since: Option<f32>,
+    fat_percentage_since: Option<f32>,
+    muscle_kg_since: Option<f32>,
+    bone_kg_since: Option<f32>,
+    fat_kg_since: Option<f32>,
+    muscle_percentage_since: Option<f32>
+}
+
+impl SinceDiffs {
+    pub fn between(current: &WheightMeasurementOutput, reference: Option<&WheightMeasurementOutput>) -> Self {
+        SinceDiffs {
+            since_reference_date: reference.map(|r| r.date),
+            wheight_kg_since: reference.map(|r| current.wheight_kg - r.wheight_kg),
+            fat_percentage_since: reference.map(|r| current.fat_percentage - r.fat_percentage),
+            muscle_kg_since: reference.map(|r| current.muscle_kg - r.muscle_kg),
+            bone_kg_since: reference.map(|r| current.bone_kg - r.bone_kg),
+            fat_kg_since: reference.map(|r| current.fat_kg - r.fat_kg),
+            muscle_percentage_since: reference.map(|r| current.muscle_percentage - r.muscle_percentage)
+        }
+    }
+}
+
+impl Validate for WheightMeasurementInput {
+    // JSON can't carry NaN/inf, but huge literals like `1e300` overflow `f32` to infinity,
+    // and a zero weight would make the derived percentages blow up
+    fn validate(&self) -> Result<(), AppError> {
+        let numbers = [
+            ("wheight_kg", self.wheight_kg),
+            ("imc", self.imc),
+            ("fat_percentage", self.fat_percentage),
+            ("water_percentage", self.water_percentage),
+            ("protein_percentage", self.protein_percentage),
+            ("metabolism_kcal", self.metabolism_kcal),
+            ("visceral_fat_index", self.visceral_fat_index),
+            ("muscle_kg", self.muscle_kg),
+            ("bone_kg", self.bone_kg),
+        ];
+        let mut errors = FieldErrors::default();
+        for (name, _) in numbers.iter().filter(|(_, v)| !v.is_finite()) {
+            errors.add(name, format!("{} must be a finite number", name));
+        }
+        if self.wheight_kg <= 0_f32 {
+            errors.add("wheight_kg", "wheight_kg must be greater than zero".to_string());
+        }
+        // catches fractions scaled twice and percents flagged as fractions, not fractions sent
+        // unflagged, which are indistinguishable from small percents
+        let percentages = [
+            ("fat_percentage", self.fat_percentage),
+            ("water_percentage", self.water_percentage),
+            ("protein_percentage", self.protein_percentage),
+        ];
+        for (name, value) in percentages.iter().filter(|(_, v)| !(0_f32..=100_f32).contains(v)) {
+            errors.add(name, format!("{} must be between 0 and 100, got {}", name, value));
+        }
+        if let Some(problem) = self.photo_url.as_deref().and_then(photo_url_problem) {
+            errors.add("photo_url", problem);
+        }
+        // `fat_kg` isn't sent, it's derived the same way it will be stored; the sum means
+        // nothing once one of its terms is already wrong
+        let composition = ["wheight_kg", "fat_percentage", "muscle_kg", "bone_kg"];
+        let components = self.muscle_kg + derive_fat_kg(self.wheight_kg, self.fat_percentage) + self.bone_kg;
+        if !composition.iter().any(|field| errors.has(field)) && components > self.wheight_kg + COMPOSITION_TOLERANCE_KG {
+            errors.add("wheight_kg", format!(
+                "muscle_kg, fat_kg and bone_kg add up to {:.1} kg, more than the {:.1} kg wheight_kg", components, self.wheight_kg
+            ));
+        }
+        errors.into_result()
+    }
+
+    fn scale_percentages(&mut self, factor: f32) {
+        self.fat_percentage *= factor;
+        self.water_percentage *= factor;
+        self.protein_percentage *= factor;
+    }
+}
+
+const MAX_PHOTO_URL_LENGTH: usize = 2048;
+
+// why `url` isn't an acceptable `photo_url`, if it isn't: an absolute http(s) URL with a host
+fn photo_url_problem(url: &str) -> Option<String> {
+    if url.len() > MAX_PHOTO_URL_LENGTH {
+        return Some(format!("photo_url can be at most {} characters", MAX_PHOTO_URL_LENGTH));
+    }
+    let rest = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://"));
+    let host = rest.map(|r| r.split(['/', '?', '#']).next().unwrap_or(""));
+    match host {
+        _ if url.chars().any(|c| c.is_whitespace() || c.is_control()) => Some("photo_url can't contain whitespace".to_string()),
+        Some(host) if !host.is_empty() && !host.starts_with(':') && !host.contains('@') => None,
+        _ => Some("photo_url must be an absolute http(s) URL".to_string())
+    }
+}
+
+// scales round each component on its own, so their sum may overshoot the total slightly
+const COMPOSITION_TOLERANCE_KG: f32 = 0.5;
+
+fn derive_fat_kg(wheight_kg: f32, fat_percentage: f32) -> f32 {
+    finite_or_zero(wheight_kg * (fat_percentage / 100_f32))
+}
+
+fn derive_muscle_percentage(muscle_kg: f32, wheight_kg: f32) -> f32 {
+    finite_or_zero(100_f32 * (muscle_kg / wheight_kg))
+}
+
+// a zero derived value is taken as missing (a legacy document) and recomputed from the raw inputs
+fn or_derived(stored: f32, derive: impl FnOnce() -> f32) -> f32 {
+    if stored == 0_f32 { derive() } else { stored }
+}
+
+// whole years, not counting the current one until its birthday has passed
+fn age_on(birth: NaiveDate, day: NaiveDate) -> u32 {
+    let years = day.year() - birth.year() - i32::from((day.month(), day.day()) < (birth.month(), birth.day()));
+    years.max(0) as u32
+}
+
+// derived values are stored and serialized, so a non-finite result is flattened to 0
+fn finite_or_zero(value: f32) -> f32 {
+    if value.is_finite() { value } else { 0_f32 }
+}
+
+// tags are matched exactly, so they are stored trimmed, lowercase and without duplicates
+fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+impl WheightMeasurementEntity {
+    pub fn from_input(input: WheightMeasurementInput) -> Self {
+        WheightMeasurementEntity {
+            _id: bson::oid::ObjectId::default(),
+            date: BsonDateTime::from_chrono(input.date),
+            wheight_kg: input.wheight_kg,
+            imc: input.imc,
+            fat_percentage: input.fat_percentage,
+            water_percentage: input.water_percentage,
+            protein_percentage: input.protein_percentage,
+            metabolism_kcal: input.metabolism_kcal,
+            visceral_fat_index: input.visceral_fat_index,
+            muscle_kg: input.muscle_kg,
+            bone_kg: input.bone_kg,
+            metabolic_age: input.metabolic_age,
+            fat_kg: derive_fat_kg(input.wheight_kg, input.fat_percentage),
+            muscle_percentage: derive_muscle_percentage(input.muscle_kg, input.wheight_kg),
+            source: input.source,
+            notes: input.notes,
+            tags: normalize_tags(input.tags),
+            photo_url: input.photo_url,
+            is_favorite: false,
+            is_locked: false,
+            version: 1,
+            updated_at: Some(BsonDateTime::now())
+        }
+    }
 }
 
 impl WheightMeasurementOutput {
     pub fn from_entity(entity: WheightMeasurementEntity) -> Self {
-        WheightMeasurementOutput {
+        let mut output = WheightMeasurementOutput {
             id: entity._id.to_string(),
             date: entity.date.to_chrono(),
             wheight_kg: entity.wheight_kg,
             imc: entity.imc,
+            imc_category: ImcCategory::from_imc(entity.imc),
+            category_label: ImcCategory::from_imc(entity.imc).map(|c| c.label(Language::En)),
             fat_percentage: entity.fat_percentage,
             water_percentage: entity.water_percentage,
             protein_percentage: entity.protein_percentage,
@@ -217,31 +1509,461 @@ impl WheightMeasurementOutput {
             muscle_kg: entity.muscle_kg,
             bone_kg: entity.bone_kg,
             metabolic_age: entity.metabolic_age,
-            fat_kg: entity.fat_kg,
-            muscle_percentage: entity.muscle_percentage,
+            fat_kg: or_derived(entity.fat_kg, || derive_fat_kg(entity.wheight_kg, entity.fat_percentage)),
+            muscle_percentage: or_derived(entity.muscle_percentage, || derive_muscle_percentage(entity.muscle_kg, entity.wheight_kg)),
+            source: entity.source,
+            notes: entity.notes,
+            tags: entity.tags,
+            photo_url: entity.photo_url,
+            is_favorite: entity.is_favorite,
+            is_locked: entity.is_locked,
+            version: entity.version,
+            updated_at: entity.updated_at.map(|d| d.to_chrono()),
+            unit: Unit::Kg,
+            chronological_age: None,
+            metabolic_age_delta: None,
             wheight_kg_diff: 0_f32,
             fat_percentage_diff: 0_f32,
             muscle_kg_diff: 0_f32,
             bone_kg_diff: 0_f32,
             fat_kg_diff: 0_f32,
-            muscle_percentage_diff: 0_f32
+            muscle_percentage_diff: 0_f32,
+            raw_diffs: None,
+            quality: Quality::Medium,
+            since: None
+        };
+        output.quality = quality::assess(&output, None);
+        output
+    }
+
+    // quality again, now that whether it is an outlier among its neighbours is known
+    pub fn with_outlier(mut self, outlier: Option<bool>) -> Self {
+        self.quality = quality::assess(&self, outlier);
+        self
+    }
+
+    // full years between `birth_date` and the measurement, and how far the scale's metabolic age is from them
+    pub fn with_age(mut self, birth_date: Option<NaiveDate>) -> Self {
+        self.chronological_age = birth_date.map(|birth| age_on(birth, self.date.date_naive()));
+        self.metabolic_age_delta = self.chronological_age.map(|age| self.metabolic_age as i32 - age as i32);
+        self
+    }
+
+    pub fn localized(mut self, language: Language) -> Self {
+        self.category_label = self.imc_category.map(|c| c.label(language));
+        self
+    }
+
+    pub fn in_unit(mut self, unit: Unit) -> Self {
+        if self.unit == unit {
+            return self;
+        }
+        self.wheight_kg = unit.convert_kg(self.wheight_kg);
+        self.muscle_kg = unit.convert_kg(self.muscle_kg);
+        self.bone_kg = unit.convert_kg(self.bone_kg);
+        self.fat_kg = unit.convert_kg(self.fat_kg);
+        self.wheight_kg_diff = unit.convert_kg(self.wheight_kg_diff);
+        self.muscle_kg_diff = unit.convert_kg(self.muscle_kg_diff);
+        self.bone_kg_diff = unit.convert_kg(self.bone_kg_diff);
+        self.fat_kg_diff = unit.convert_kg(self.fat_kg_diff);
+        self.raw_diffs = self.raw_diffs.map(|d| d.in_unit(unit));
+        if let Some(since) = self.since.as_mut() {
+            since.wheight_kg_since = since.wheight_kg_since.map(|v| unit.convert_kg(v));
+            since.muscle_kg_since = since.muscle_kg_since.map(|v| unit.convert_kg(v));
+            since.bone_kg_since = since.bone_kg_since.map(|v| unit.convert_kg(v));
+            since.fat_kg_since = since.fat_kg_since.map(|v| unit.convert_kg(v));
         }
+        self.unit = unit;
+        self
+    }
+
+    // fills the `_diff` fields with the change since `previous`, those within the noise
+    // thresholds as 0; `keep_raw` keeps the unsuppressed ones too
+    pub fn set_diffs(&mut self, previous: &WheightMeasurementOutput, thresholds: &NoiseThresholds, keep_raw: bool) {
+        let raw = MeasurementDiff::between(previous, self);
+        let diff = raw.clone().suppress_noise(thresholds);
+        self.wheight_kg_diff = diff.wheight_kg;
+        self.fat_percentage_diff = diff.fat_percentage;
+        self.muscle_kg_diff = diff.muscle_kg;
+        self.bone_kg_diff = diff.bone_kg;
+        self.fat_kg_diff = diff.fat_kg;
+        self.muscle_percentage_diff = diff.muscle_percentage;
+        self.raw_diffs = keep_raw.then_some(raw);
     }
 }
 
-// the output to our `create_user` handler
-#[derive(Serialize)]
-struct WheightMeasurementMovingAvarageOutput {
-    id: u64,
-    date: DateTime<Utc>,
-    wheight_kg: f32,
-    fat_kg: f32,
-    muscle_kg: f32,
-    muscle_percentage: f32,
-    fat_percentage: f32,
-    wheight_kg_diff: f32,
-    fat_kg_diff: f32,
-    muscle_kg_diff: f32,
-    muscle_percentage_diff: f32,
-    fat_percentage_diff: f32
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use mongodb::options::ServerAddress;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use super::*;
+    use repository::fake::InMemoryMeasurements;
+
+    // a client that is never reached: only the repository holds data, anything that falls
+    // through to a collection fails fast
+    fn test_state(measurements: Arc<InMemoryMeasurements>) -> AppState {
+        let options = ClientOptions::builder()
+            .hosts(vec![ServerAddress::Tcp { host: "127.0.0.1".to_string(), port: Some(1) }])
+            .server_selection_timeout(Duration::from_millis(50))
+            .build();
+        let db = Client::with_options(options).unwrap().database("test");
+        AppState {
+            config: Arc::new(Config::from_env()),
+            collection: db.collection("Wheights"),
+            measurements,
+            profiles: db.collection("Profile"),
+            aggregates: db.collection("daily_aggregates"),
+            audit: db.collection("audit"),
+            healthcheck: db.collection("_healthcheck"),
+            latest: Arc::new(RwLock::new(None)),
+            profile: Arc::new(RwLock::new(Some(ProfileEntity::default()))),
+            facets: Arc::new(RwLock::new(None))
+        }
+    }
+
+    async fn send(state: AppState, request: Request<Body>) -> (StatusCode, Value) {
+        let response = routes::router().with_state(state).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        if body.is_empty() {
+            return (status, Value::Null);
+        }
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn clamped_param_clamps_numbers_and_rejects_the_rest() {
+        let limit = |value| clamped_param("limit", value, 50, 1, 200);
+        assert_eq!(limit(None).unwrap(), 50);
+        assert_eq!(limit(Some(" 20 ")).unwrap(), 20);
+        assert_eq!(limit(Some("+20")).unwrap(), 20);
+        assert_eq!(limit(Some("0")).unwrap(), 1);
+        assert_eq!(limit(Some("-5")).unwrap(), 1);
+        assert_eq!(limit(Some("1000")).unwrap(), 200);
+        // beyond `i64` still clamps, it's a number all the same
+        assert_eq!(limit(Some("99999999999999999999999")).unwrap(), 200);
+        assert_eq!(limit(Some("-99999999999999999999999")).unwrap(), 1);
+        assert_eq!(clamped_param("page", None, 500, 0, 10).unwrap(), 10);
+        for bad in ["", "-", "+", "1.5", "ten", "1e3", "--1"] {
+            assert!(matches!(limit(Some(bad)), Err(AppError::BadRequest(_))), "{:?}", bad);
+        }
+    }
+
+    #[tokio::test]
+    async fn get_by_id_of_a_missing_measurement_is_404() {
+        let state = test_state(Arc::default());
+        let (status, body) = send(state, get("/v1/weight/measurement/64b7f0c2a1e3d94f2c8b4567?unit=kg")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, json!({ "code": "not_found", "message": "Not Found" }));
+    }
+
+    #[tokio::test]
+    async fn get_by_malformed_id_is_400() {
+        let state = test_state(Arc::default());
+        let (status, body) = send(state, get("/v1/weight/measurement/not-an-id?unit=kg")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, json!({ "code": "invalid_id", "message": "Invalid id: not-an-id" }));
+    }
+
+    fn create(payload: &Value) -> Request<Body> {
+        Request::post("/v1/weight/measurement")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap()
+    }
+
+    fn measurement_payload() -> Value {
+        json!({
+            "date": "2024-01-01T07:30:00Z",
+            "wheight_kg": 80.0,
+            "imc": 24.7,
+            "fat_percentage": 20.0,
+            "water_percentage": 55.0,
+            "protein_percentage": 18.0,
+            "metabolism_kcal": 1700.0,
+            "visceral_fat_index": 8.0,
+            "muscle_kg": 58.0,
+            "bone_kg": 3.0,
+            "metabolic_age": 30
+        })
+    }
+
+    #[tokio::test]
+    async fn create_is_201_with_an_id_the_get_accepts() {
+        let measurements = Arc::new(InMemoryMeasurements::default());
+        let (status, body) = send(test_state(measurements.clone()), create(&measurement_payload())).await;
+
+        assert_eq!(status, StatusCode::CREATED);
+        let id = body["id"].as_str().expect("id is a string");
+        let oid = parse_object_id(id).expect("id parses as an ObjectId");
+        let stored = measurements.measurements.lock().unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0]._id, oid);
+    }
+
+    #[tokio::test]
+    async fn delete_removes_the_measurement_clears_the_latest_cache_and_is_audited() {
+        let measurements = Arc::new(InMemoryMeasurements::default());
+        let state = test_state(measurements.clone());
+        let (_, body) = send(state.clone(), create(&measurement_payload())).await;
+        let id = body["id"].as_str().unwrap().to_string();
+        let oid = parse_object_id(&id).unwrap();
+        let created = measurements.measurements.lock().unwrap()[0].clone();
+        state.cache_latest(WheightMeasurementOutput::from_entity(created)).await;
+
+        let delete = || Request::delete(format!("/v1/weight/measurement/{}", id)).body(Body::empty()).unwrap();
+        let (status, _) = send(state.clone(), delete()).await;
+
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(measurements.measurements.lock().unwrap().is_empty());
+        assert!(state.latest.read().await.is_none());
+        let audit: Vec<bson::Document> = measurements.audit.lock().unwrap().iter().map(|e| bson::to_document(e).unwrap()).collect();
+        assert_eq!(audit.len(), 2);
+        assert_eq!(audit[1].get_str("operation"), Ok("delete"));
+        assert_eq!(audit[1].get_object_id("measurement_id"), Ok(oid));
+        assert_eq!(audit[1].get_document("before").and_then(|b| b.get_object_id("_id")), Ok(oid));
+        assert_eq!(audit[1].get("after"), Some(&bson::Bson::Null));
+
+        // repeating it only repeats the cleanup
+        let (status, _) = send(state.clone(), delete()).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert_eq!(measurements.audit.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn delete_of_a_missing_measurement_is_404() {
+        let state = test_state(Arc::default());
+        let request = Request::delete("/v1/weight/measurement/64b7f0c2a1e3d94f2c8b4567").body(Body::empty()).unwrap();
+        let (status, _) = send(state, request).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn delete_of_a_locked_measurement_is_423_and_keeps_it() {
+        let measurements = Arc::new(InMemoryMeasurements::default());
+        let state = test_state(measurements.clone());
+        let (_, body) = send(state.clone(), create(&measurement_payload())).await;
+        let id = body["id"].as_str().unwrap().to_string();
+        measurements.measurements.lock().unwrap()[0].is_locked = true;
+
+        let request = Request::delete(format!("/v1/weight/measurement/{}", id)).body(Body::empty()).unwrap();
+        let (status, body) = send(state, request).await;
+
+        assert_eq!(status, StatusCode::LOCKED);
+        assert_eq!(body["code"], "locked");
+        assert_eq!(measurements.measurements.lock().unwrap().len(), 1);
+        // only the creation
+        assert_eq!(measurements.audit.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn update_bumps_the_version_and_is_audited() {
+        let measurements = Arc::new(InMemoryMeasurements::default());
+        let state = test_state(measurements.clone());
+        let (_, body) = send(state.clone(), create(&measurement_payload())).await;
+        let id = body["id"].as_str().unwrap().to_string();
+
+        let mut heavier = measurement_payload();
+        heavier["wheight_kg"] = json!(90.0);
+        let request = Request::put(format!("/v1/weight/measurement/{}", id))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(heavier.to_string()))
+            .unwrap();
+        let (status, body) = send(state, request).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["wheight_kg"], 90.0);
+        let stored = measurements.measurements.lock().unwrap()[0].clone();
+        assert_eq!((stored.wheight_kg, stored.version), (90.0, 2));
+        let audit = measurements.audit.lock().unwrap();
+        let update = bson::to_document(&audit[1]).unwrap();
+        assert_eq!(update.get_str("operation"), Ok("update"));
+        assert_eq!(update.get_document("before").and_then(|b| b.get_f64("wheight_kg")), Ok(80.0));
+    }
+
+    #[tokio::test]
+    async fn update_of_a_locked_measurement_is_423_and_keeps_it() {
+        let measurements = Arc::new(InMemoryMeasurements::default());
+        let state = test_state(measurements.clone());
+        let (_, body) = send(state.clone(), create(&measurement_payload())).await;
+        let id = body["id"].as_str().unwrap().to_string();
+        measurements.measurements.lock().unwrap()[0].is_locked = true;
+
+        let mut heavier = measurement_payload();
+        heavier["wheight_kg"] = json!(90.0);
+        let request = Request::put(format!("/v1/weight/measurement/{}", id))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(heavier.to_string()))
+            .unwrap();
+        let (status, body) = send(state, request).await;
+
+        assert_eq!(status, StatusCode::LOCKED);
+        assert_eq!(body["code"], "locked");
+        assert_eq!(measurements.measurements.lock().unwrap()[0].wheight_kg, 80.0);
+        assert_eq!(measurements.audit.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn reschedule_of_a_locked_measurement_is_423_and_keeps_its_date() {
+        let measurements = Arc::new(InMemoryMeasurements::default());
+        let state = test_state(measurements.clone());
+        let (_, body) = send(state.clone(), create(&measurement_payload())).await;
+        let id = body["id"].as_str().unwrap().to_string();
+        measurements.measurements.lock().unwrap()[0].is_locked = true;
+
+        let mut later = measurement_payload();
+        later["date"] = json!("2024-01-02T07:30:00Z");
+        send(state.clone(), create(&later)).await;
+
+        // onto a free date, and onto the other measurement's
+        for date in ["2024-01-05T07:30:00Z", "2024-01-02T07:30:00Z"] {
+            let request = Request::patch(format!("/v1/weight/measurement/{}/date", id))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json!({ "date": date }).to_string()))
+                .unwrap();
+            let (status, body) = send(state.clone(), request).await;
+
+            assert_eq!(status, StatusCode::LOCKED);
+            assert_eq!(body["code"], "locked");
+        }
+        let date = measurements.measurements.lock().unwrap()[0].date.to_chrono();
+        assert_eq!(date.to_rfc3339(), "2024-01-01T07:30:00+00:00");
+    }
+
+    #[tokio::test]
+    async fn favorite_of_a_locked_measurement_is_423_and_keeps_its_version() {
+        let measurements = Arc::new(InMemoryMeasurements::default());
+        let state = test_state(measurements.clone());
+        let (_, body) = send(state.clone(), create(&measurement_payload())).await;
+        let id = body["id"].as_str().unwrap().to_string();
+        measurements.measurements.lock().unwrap()[0].is_locked = true;
+
+        let request = Request::post(format!("/v1/weight/measurement/{}/favorite", id)).body(Body::empty()).unwrap();
+        let (status, body) = send(state, request).await;
+
+        assert_eq!(status, StatusCode::LOCKED);
+        assert_eq!(body["code"], "locked");
+        let stored = measurements.measurements.lock().unwrap()[0].clone();
+        assert_eq!((stored.is_favorite, stored.version), (false, 1));
+        assert_eq!(measurements.audit.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn locking_twice_bumps_the_version_once() {
+        let measurements = Arc::new(InMemoryMeasurements::default());
+        let state = test_state(measurements.clone());
+        let (_, body) = send(state.clone(), create(&measurement_payload())).await;
+        let id = body["id"].as_str().unwrap().to_string();
+
+        for _ in 0..2 {
+            let request = Request::post(format!("/v1/weight/measurement/{}/lock", id)).body(Body::empty()).unwrap();
+            let (status, body) = send(state.clone(), request).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body["version"], 2);
+        }
+        let stored = measurements.measurements.lock().unwrap()[0].clone();
+        assert!(stored.is_locked);
+        // the creation and the first lock
+        assert_eq!(measurements.audit.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn reschedule_onto_another_measurement_is_409_and_keeps_its_date() {
+        let measurements = Arc::new(InMemoryMeasurements::default());
+        let state = test_state(measurements.clone());
+        send(state.clone(), create(&measurement_payload())).await;
+        let mut later = measurement_payload();
+        later["date"] = json!("2024-01-02T07:30:00Z");
+        let (_, body) = send(state.clone(), create(&later)).await;
+        let id = body["id"].as_str().unwrap().to_string();
+
+        let request = Request::patch(format!("/v1/weight/measurement/{}/date", id))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({ "date": "2024-01-01T07:30:00Z" }).to_string()))
+            .unwrap();
+        let (status, body) = send(state, request).await;
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "conflict");
+        let moved = measurements.measurements.lock().unwrap()[1].date.to_chrono();
+        assert_eq!(moved.to_rfc3339(), "2024-01-02T07:30:00+00:00");
+    }
+
+    #[tokio::test]
+    async fn unknown_route_is_a_json_404() {
+        let state = test_state(Arc::default());
+        let (status, body) = send(state, get("/v1/weight/nowhere")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, json!({ "code": "not_found", "message": "Route not found: GET /v1/weight/nowhere" }));
+    }
+
+    #[tokio::test]
+    async fn only_dataset_shaped_paths_name_an_unknown_dataset() {
+        let datasets = datasets::Datasets::default();
+        let app = routes::router()
+            .layer(middleware::from_fn(move |req, next| datasets::dispatch(datasets.clone(), req, next)))
+            .with_state(test_state(Arc::default()));
+
+        for (uri, status, code) in [
+            ("/v1/nowhere", StatusCode::NOT_FOUND, "not_found"),
+            ("/v1/wieght/measurement", StatusCode::NOT_FOUND, "not_found"),
+            ("/v1/dog/weight/measurement", StatusCode::BAD_REQUEST, "bad_request")
+        ] {
+            let response = app.clone().oneshot(get(uri)).await.unwrap();
+            assert_eq!(response.status(), status, "{}", uri);
+            let body: Value = serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await.unwrap()).unwrap();
+            assert_eq!(body["code"], code, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn admin_routes_challenge_a_wrong_token_and_are_forbidden_without_one_configured() {
+        let recalculate = |token: &str| Request::post("/v1/weight/measurement/recalculate-imc")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        let mut disabled = test_state(Arc::default());
+        disabled.config = Arc::new(Config { admin_token: None, ..Config::from_env() });
+        let (status, body) = send(disabled, recalculate("secret")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], "forbidden");
+
+        let mut enabled = test_state(Arc::default());
+        enabled.config = Arc::new(Config { admin_token: Some("secret".to_string()), ..Config::from_env() });
+        let response = routes::router().with_state(enabled).oneshot(recalculate("guess")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+    }
+
+    #[tokio::test]
+    async fn healthy_range_needs_a_profile_height() {
+        let state = test_state(Arc::default());
+        let (status, body) = send(state, get("/v1/weight/measurement/healthy-range?unit=kg")).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "validation_failed");
+    }
+
+    #[tokio::test]
+    async fn healthy_range_places_the_latest_weight_in_the_normal_bmi_band() {
+        let measurements = Arc::new(InMemoryMeasurements::default());
+        let state = test_state(measurements.clone());
+        *state.profile.write().await = Some(ProfileEntity { height_cm: Some(180_f32), ..ProfileEntity::default() });
+        send(state.clone(), create(&measurement_payload())).await;
+        let created = measurements.measurements.lock().unwrap()[0].clone();
+        state.cache_latest(WheightMeasurementOutput::from_entity(created)).await;
+
+        let (status, body) = send(state, get("/v1/weight/measurement/healthy-range?unit=kg")).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!((body["min_kg"].as_f64().unwrap() - 59.94).abs() < 0.01);
+        assert!((body["max_kg"].as_f64().unwrap() - 80.68).abs() < 0.01);
+        assert_eq!(body["latest_status"]["position"], "within");
+    }
+}
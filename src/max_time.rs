@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use mongodb::options::{AggregateOptions, CountOptions, DistinctOptions, FindOneOptions, FindOptions, SelectionCriteria};
+
+use crate::AppState;
+
+// read options MongoDB can enforce a server-side time limit on and route to a secondary
+pub trait MaxTime {
+    fn set_max_time(&mut self, max_time: Duration);
+    fn set_selection_criteria(&mut self, criteria: SelectionCriteria);
+}
+
+macro_rules! impl_max_time {
+    ($($options:ty),*) => {
+        $(impl MaxTime for $options {
+            fn set_max_time(&mut self, max_time: Duration) {
+                self.max_time = Some(max_time);
+            }
+
+            fn set_selection_criteria(&mut self, criteria: SelectionCriteria) {
+                self.selection_criteria = Some(criteria);
+            }
+        })*
+    };
+}
+
+impl_max_time!(FindOptions, FindOneOptions, AggregateOptions, CountOptions, DistinctOptions);
+
+impl AppState {
+    // `options` with `DB_OP_TIMEOUT_MS` as their `maxTimeMS`, so a pathological query is aborted
+    // by the server instead of eating the whole request budget, read with `MONGODB_READ_PREFERENCE`
+    pub fn bounded<O: MaxTime>(&self, options: O) -> O {
+        let mut options = self.bounded_primary(options);
+        if let Some(criteria) = self.config.read_preference.selection_criteria() {
+            options.set_selection_criteria(criteria);
+        }
+        options
+    }
+
+    // for reads that must see every write already acknowledged, whatever the read preference
+    pub fn bounded_primary<O: MaxTime>(&self, mut options: O) -> O {
+        options.set_max_time(self.config.db_op_timeout);
+        options
+    }
+}
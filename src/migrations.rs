@@ -0,0 +1,43 @@
+use mongodb::{
+    bson::{doc, DateTime as BsonDateTime, Document},
+    Collection,
+};
+
+use crate::WheightMeasurementEntity;
+
+// marker documents of the migrations already applied, one per migration version
+const BACKFILL_DERIVED_FIELDS: &str = "backfill_derived_fields_v1";
+
+// runs the pending migrations once; a failed one leaves no marker and is retried on the next start
+pub async fn run(measurements: Collection<WheightMeasurementEntity>, migrations: Collection<Document>) {
+    match backfill_derived_fields(&measurements, &migrations).await {
+        Ok(Some(n)) => tracing::info!("Migration {} backfilled {} measurements", BACKFILL_DERIVED_FIELDS, n),
+        Ok(None) => {},
+        Err(e) => tracing::warn!("Error running migration {}: {}", BACKFILL_DERIVED_FIELDS, e)
+    }
+}
+
+// documents written before `fat_kg`/`muscle_percentage` were stored get them computed from
+// their raw inputs, the same way `from_input` derives them
+async fn backfill_derived_fields(measurements: &Collection<WheightMeasurementEntity>, migrations: &Collection<Document>) -> mongodb::error::Result<Option<u64>> {
+    if migrations.find_one(doc! { "_id": BACKFILL_DERIVED_FIELDS }, None).await?.is_some() {
+        return Ok(None);
+    }
+
+    let filter = doc! { "$or": [
+        { "fat_kg": { "$exists": false } },
+        { "muscle_percentage": { "$exists": false } }
+    ] };
+    let pipeline = vec![doc! { "$set": {
+        "fat_kg": { "$ifNull": ["$fat_kg", { "$multiply": ["$wheight_kg", { "$divide": ["$fat_percentage", 100] }] }] },
+        "muscle_percentage": { "$ifNull": ["$muscle_percentage", { "$cond": [
+            { "$gt": ["$wheight_kg", 0] },
+            { "$multiply": [100, { "$divide": ["$muscle_kg", "$wheight_kg"] }] },
+            0
+        ] }] }
+    } }];
+    let result = measurements.update_many(filter, pipeline, None).await?;
+
+    migrations.insert_one(doc! { "_id": BACKFILL_DERIVED_FIELDS, "applied_at": BsonDateTime::now() }, None).await?;
+    Ok(Some(result.modified_count))
+}
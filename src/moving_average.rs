@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use mongodb::{bson::{doc, DateTime as BsonDateTime}, options::FindOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::{daily_values::{collapsed_wheights, Collapse}, error::AppError, units::{OutputUnit, Unit}, derive_fat_kg, derive_muscle_percentage, or_derived, AppState, WheightMeasurementEntity};
+
+const DEFAULT_WINDOW: usize = 7;
+const MAX_WINDOW: usize = 90;
+
+// metrics that can be averaged, and whether they are masses (converted to the output unit)
+const METRICS: [(&str, bool); 5] = [
+    ("wheight_kg", true),
+    ("fat_kg", true),
+    ("muscle_kg", true),
+    ("fat_percentage", false),
+    ("muscle_percentage", false),
+];
+
+#[derive(Deserialize)]
+struct WheightPointEntity {
+    date: BsonDateTime,
+    wheight_kg: f32
+}
+
+fn metric_value(entity: &WheightMeasurementEntity, metric: &str) -> f32 {
+    match metric {
+        "wheight_kg" => entity.wheight_kg,
+        "fat_kg" => or_derived(entity.fat_kg, || derive_fat_kg(entity.wheight_kg, entity.fat_percentage)),
+        "muscle_kg" => entity.muscle_kg,
+        "fat_percentage" => entity.fat_percentage,
+        "muscle_percentage" => or_derived(entity.muscle_percentage, || derive_muscle_percentage(entity.muscle_kg, entity.wheight_kg)),
+        _ => unreachable!("metric names are validated against METRICS")
+    }
+}
+
+// average of `values` over the `window` entries ending at `i` (fewer at the start)
+fn trailing_average(values: &[f32], i: usize, window: usize) -> f32 {
+    let trailing = &values[(i + 1).saturating_sub(window)..=i];
+    trailing.iter().sum::<f32>() / trailing.len() as f32
+}
+
+#[derive(Deserialize)]
+pub struct MovingAverageQuery {
+    window: Option<usize>,
+    // comma-separated subset of `METRICS`, all of them when absent
+    metrics: Option<String>
+}
+
+fn parse_metrics(metrics: Option<&str>) -> Result<Vec<(&'static str, bool)>, AppError> {
+    let Some(metrics) = metrics else {
+        return Ok(METRICS.to_vec());
+    };
+    metrics.split(',').map(str::trim).filter(|m| !m.is_empty())
+        .map(|name| METRICS.iter().find(|(known, _)| *known == name).copied()
+            .ok_or_else(|| AppError::BadRequest(format!(
+                "Unknown metric '{}', expected some of {}", name, METRICS.map(|(m, _)| m).join(", ")
+            ))))
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(|selected| if selected.is_empty() {
+            Err(AppError::BadRequest("metrics must name at least one metric".to_string()))
+        } else {
+            Ok(selected)
+        })
+}
+
+#[derive(Serialize)]
+struct MovingAveragePoint {
+    date: DateTime<Utc>,
+    unit: Unit,
+    #[serde(flatten)]
+    averages: BTreeMap<&'static str, f32>
+}
+
+// average of each selected metric over the trailing `window` measurements (fewer at the start),
+// or with `?daily=` over the trailing `window` days of one weight each
+pub async fn get_moving_average(State(state): State<AppState>, Query(query): Query<MovingAverageQuery>, collapse: Collapse, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let window = query.window.unwrap_or(DEFAULT_WINDOW);
+    if !(1..=MAX_WINDOW).contains(&window) {
+        return Err(AppError::BadRequest(format!("window must be between 1 and {}", MAX_WINDOW)));
+    }
+    let daily = collapse.requested();
+    let metrics = match (daily, query.metrics.as_deref()) {
+        (Some(_), None) => vec![METRICS[0]],
+        (Some(_), Some(names)) => {
+            let metrics = parse_metrics(Some(names))?;
+            // the day's value is picked by weight, the other metrics have no daily series
+            if metrics.iter().any(|(metric, _)| *metric != "wheight_kg") {
+                return Err(AppError::BadRequest("daily only applies to wheight_kg".to_string()));
+            }
+            metrics
+        },
+        (None, names) => parse_metrics(names)?
+    };
+
+    let (dates, series): (Vec<DateTime<Utc>>, Vec<Vec<f32>>) = match daily {
+        Some(daily) => {
+            let days = collapsed_wheights(&state, doc! {}, daily).await?;
+            (days.iter().map(|p| p.date).collect(), vec![days.iter().map(|p| p.wheight_kg as f32).collect()])
+        },
+        None => {
+            let options = FindOptions::builder().sort(doc! { "date": 1 }).build();
+            let mut cursor = state.timed("find", "all", state.collection.find(None, state.bounded(options))).await?;
+            let mut measurements = Vec::new();
+            while cursor.advance().await? {
+                measurements.push(cursor.deserialize_current()?);
+            }
+            let series = metrics.iter()
+                .map(|&(metric, _)| measurements.iter().map(|m| metric_value(m, metric)).collect())
+                .collect();
+            (measurements.iter().map(|m| m.date.to_chrono()).collect(), series)
+        }
+    };
+    let points: Vec<MovingAveragePoint> = (0..dates.len())
+        .map(|i| {
+            let averages = metrics.iter().zip(&series)
+                .map(|(&(metric, is_mass), values)| {
+                    let avg = trailing_average(values, i, window);
+                    (metric, if is_mass { unit.convert_kg(avg) } else { avg })
+                })
+                .collect();
+            MovingAveragePoint { date: dates[i], unit, averages }
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(points)))
+}
+
+const DEFAULT_FAST: usize = 7;
+const DEFAULT_SLOW: usize = 30;
+
+#[derive(Deserialize)]
+pub struct CrossoverQuery {
+    // windows in measurements (days with `?daily=`), like `window` above
+    fast: Option<usize>,
+    slow: Option<usize>
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+enum CrossDirection {
+    // the fast average fell below the slow one, loss is speeding up (or gain slowing down)
+    Downward,
+    Upward
+}
+
+#[derive(Serialize)]
+struct Crossover {
+    date: DateTime<Utc>,
+    direction: CrossDirection,
+    fast_wheight_kg: f32,
+    slow_wheight_kg: f32,
+    unit: Unit
+}
+
+// `(index, direction, fast average, slow average)` of every crossing of the two trailing
+// averages of `weights`, from where the slow window first fills up; a touch without crossing
+// isn't a signal
+fn crossings(weights: &[f32], fast: usize, slow: usize) -> Vec<(usize, CrossDirection, f32, f32)> {
+    let mut crossings = Vec::new();
+    // the side the fast average was last seen on
+    let mut above: Option<bool> = None;
+    for i in slow.saturating_sub(1)..weights.len() {
+        let (fast_avg, slow_avg) = (trailing_average(weights, i, fast), trailing_average(weights, i, slow));
+        if fast_avg == slow_avg {
+            continue;
+        }
+        let now_above = fast_avg > slow_avg;
+        if above.is_some_and(|was_above| was_above != now_above) {
+            let direction = if now_above { CrossDirection::Upward } else { CrossDirection::Downward };
+            crossings.push((i, direction, fast_avg, slow_avg));
+        }
+        above = Some(now_above);
+    }
+    crossings
+}
+
+// measurements where the fast moving average of `wheight_kg` crosses the slow one, oldest
+// first; crossings before the slow window first fills up are start-up noise and not reported
+pub async fn get_crossovers(State(state): State<AppState>, Query(query): Query<CrossoverQuery>, collapse: Collapse, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let (fast, slow) = (query.fast.unwrap_or(DEFAULT_FAST), query.slow.unwrap_or(DEFAULT_SLOW));
+    if !(1..=MAX_WINDOW).contains(&fast) || !(1..=MAX_WINDOW).contains(&slow) {
+        return Err(AppError::BadRequest(format!("fast and slow must be between 1 and {}", MAX_WINDOW)));
+    }
+    if fast >= slow {
+        return Err(AppError::BadRequest("fast must be smaller than slow".to_string()));
+    }
+
+    let (mut dates, mut weights) = (Vec::new(), Vec::new());
+    if let Some(daily) = collapse.requested() {
+        for p in collapsed_wheights(&state, doc! {}, daily).await? {
+            dates.push(p.date);
+            weights.push(p.wheight_kg as f32);
+        }
+    } else {
+        let options = FindOptions::builder().sort(doc! { "date": 1 }).projection(doc! { "date": 1, "wheight_kg": 1 }).build();
+        let mut cursor = state.timed("find", "all", state.collection.clone_with_type::<WheightPointEntity>().find(None, state.bounded(options))).await?;
+        while cursor.advance().await? {
+            let m = cursor.deserialize_current()?;
+            dates.push(m.date.to_chrono());
+            weights.push(m.wheight_kg);
+        }
+    }
+
+    let crossovers: Vec<Crossover> = crossings(&weights, fast, slow).into_iter()
+        .map(|(i, direction, fast_avg, slow_avg)| Crossover {
+            date: dates[i],
+            direction,
+            fast_wheight_kg: unit.convert_kg(fast_avg),
+            slow_wheight_kg: unit.convert_kg(slow_avg),
+            unit
+        })
+        .collect();
+    Ok((StatusCode::OK, Json(crossovers)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailing_average_shortens_at_the_start() {
+        let values = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(trailing_average(&values, 0, 3), 1.0);
+        assert_eq!(trailing_average(&values, 1, 3), 1.5);
+        assert_eq!(trailing_average(&values, 3, 3), 3.0);
+        assert_eq!(trailing_average(&values, 3, 1), 4.0);
+        assert_eq!(trailing_average(&values, 3, 10), 2.5);
+    }
+
+    #[test]
+    fn crossings_follow_the_fast_average_across_the_slow_one() {
+        // falls, then climbs back well above where it started
+        let weights = [80.0, 80.0, 80.0, 79.0, 78.0, 78.0, 80.0, 82.0, 82.0];
+        let found: Vec<(usize, CrossDirection)> = crossings(&weights, 1, 3).into_iter().map(|(i, d, _, _)| (i, d)).collect();
+        assert_eq!(found, vec![(6, CrossDirection::Upward)]);
+        let (_, _, fast, slow) = crossings(&weights, 1, 3)[0];
+        assert!(fast > slow);
+    }
+
+    #[test]
+    fn no_crossings_without_enough_points_or_movement() {
+        assert!(crossings(&[], 1, 3).is_empty());
+        assert!(crossings(&[80.0, 79.0], 1, 3).is_empty());
+        // a constant series only ever touches
+        assert!(crossings(&[80.0; 10], 2, 5).is_empty());
+        // a steady fall keeps the fast average below the slow one, a side isn't a crossing
+        assert!(crossings(&[84.0, 83.0, 82.0, 81.0, 80.0, 79.0], 1, 3).is_empty());
+    }
+
+    #[test]
+    fn a_touch_between_two_sides_is_skipped() {
+        // below, level at index 4, below again: no crossing
+        let weights = [80.0, 80.0, 79.0, 79.0, 79.0, 78.0];
+        assert!(crossings(&weights, 1, 3).iter().all(|(i, _, _, _)| *i != 4));
+        assert!(crossings(&weights, 1, 3).is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::{error::AppError, rounding::{self, Decimals}};
+
+// body formats the single-get and list reads can answer in, JSON unless the client asks otherwise
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    // the `rounding` middleware only rewrites JSON, so XML is rounded while it's rendered, to the
+    // decimals the middleware read from `?round=`
+    Xml { decimals: Option<u32> }
+}
+
+impl Format {
+    // first acceptable media type in `Accept` order, quality values aren't weighed
+    fn from_accept(accept: &str, decimals: Option<u32>) -> Option<Self> {
+        accept.split(',')
+            .map(|range| range.split(';').next().unwrap_or("").trim().to_ascii_lowercase())
+            .find_map(|media_type| match media_type.as_str() {
+                "application/json" | "application/*" | "*/*" => Some(Format::Json),
+                "application/xml" | "text/xml" => Some(Format::Xml { decimals }),
+                _ => None
+            })
+    }
+
+    // `root` names the XML document element, the JSON body has none
+    pub fn render<T: Serialize>(self, status: StatusCode, root: &str, value: &T) -> Result<Response, AppError> {
+        let mut response = match self {
+            Format::Json => (status, Json(value)).into_response(),
+            Format::Xml { decimals } => {
+                let xml = match decimals {
+                    Some(decimals) => {
+                        let mut value = serde_json::to_value(value).map_err(|e| AppError::Internal(e.to_string()))?;
+                        rounding::round_value(&mut value, decimals);
+                        quick_xml::se::to_string_with_root(root, &value)
+                    },
+                    None => quick_xml::se::to_string_with_root(root, value)
+                }.map_err(|e| AppError::Internal(e.to_string()))?;
+                (status, [(header::CONTENT_TYPE, "application/xml")], xml).into_response()
+            }
+        };
+        response.headers_mut().insert(header::VARY, HeaderValue::from_static("accept"));
+        Ok(response)
+    }
+}
+
+pub struct Negotiated(pub Format);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Negotiated {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let accept = match parts.headers.get(header::ACCEPT).map(|v| v.to_str()) {
+            None => return Ok(Negotiated(Format::Json)),
+            Some(Ok(accept)) if accept.trim().is_empty() => return Ok(Negotiated(Format::Json)),
+            Some(Ok(accept)) => accept,
+            Some(Err(_)) => return Err(AppError::BadRequest("Accept header isn't valid ASCII".to_string()))
+        };
+        let decimals = parts.extensions.get::<Decimals>().map(|d| d.0);
+        Format::from_accept(accept, decimals)
+            .map(Negotiated)
+            .ok_or_else(|| AppError::NotAcceptable(format!("Can't produce {}, expected application/json or application/xml", accept)))
+    }
+}
+
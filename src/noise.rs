@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{error::AppError, AppState};
+
+// smallest change of each metric worth reporting as one, in kg or percentage points; a `_diff`
+// below it is reported as 0. Unset means no threshold, each one is set on its own
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug)]
+pub struct NoiseThresholds {
+    pub wheight_kg: Option<f32>,
+    pub fat_percentage: Option<f32>,
+    pub muscle_kg: Option<f32>,
+    pub bone_kg: Option<f32>,
+    pub fat_kg: Option<f32>,
+    pub muscle_percentage: Option<f32>
+}
+
+impl NoiseThresholds {
+    // each threshold set here, `fallback`'s where it isn't
+    pub fn or(self, fallback: NoiseThresholds) -> Self {
+        NoiseThresholds {
+            wheight_kg: self.wheight_kg.or(fallback.wheight_kg),
+            fat_percentage: self.fat_percentage.or(fallback.fat_percentage),
+            muscle_kg: self.muscle_kg.or(fallback.muscle_kg),
+            bone_kg: self.bone_kg.or(fallback.bone_kg),
+            fat_kg: self.fat_kg.or(fallback.fat_kg),
+            muscle_percentage: self.muscle_percentage.or(fallback.muscle_percentage)
+        }
+    }
+
+    fn values(&self) -> [(&'static str, Option<f32>); 6] {
+        [
+            ("wheight_kg", self.wheight_kg),
+            ("fat_percentage", self.fat_percentage),
+            ("muscle_kg", self.muscle_kg),
+            ("bone_kg", self.bone_kg),
+            ("fat_kg", self.fat_kg),
+            ("muscle_percentage", self.muscle_percentage)
+        ]
+    }
+
+    pub fn problem(&self) -> Option<String> {
+        self.values().iter()
+            .find(|(_, t)| t.is_some_and(|t| !t.is_finite() || t < 0_f32))
+            .map(|(name, _)| format!("{} threshold must be zero or positive", name))
+    }
+}
+
+// `diff`, or 0 when it's smaller than `threshold`
+pub fn suppress(diff: f32, threshold: Option<f32>) -> f32 {
+    match threshold {
+        Some(t) if diff.abs() < t => 0_f32,
+        _ => diff
+    }
+}
+
+impl AppState {
+    // the profile's thresholds, the `NOISE_THRESHOLD_*` environment for those it doesn't set
+    pub async fn noise_thresholds(&self) -> Result<NoiseThresholds, AppError> {
+        Ok(self.profile().await?.noise_thresholds.or(self.config.noise_thresholds))
+    }
+}
@@ -0,0 +1,89 @@
+use std::io;
+
+use serde::Serialize;
+use serde_json::ser::{Formatter, Serializer};
+
+// like serde_json's compact output, but writes floats in plain decimal notation where serde_json's shortest representation would
+// switch to an exponent (`1.2e-7`, `1e16`), which some clients parse wrongly. The digits are the
+// same shortest round-trip ones, only the notation differs; a float always keeps a decimal point
+// and `-0.0` is written as `0.0`. Non-finite values never get here, serde_json writes them as null
+pub struct FixedPointFormatter;
+
+fn fixed_point(formatted: String) -> String {
+    let formatted = if formatted == "-0" { "0".to_string() } else { formatted };
+    if formatted.contains('.') {
+        formatted
+    } else {
+        formatted + ".0"
+    }
+}
+
+impl Formatter for FixedPointFormatter {
+    fn write_f32<W: ?Sized + io::Write>(&mut self, writer: &mut W, value: f32) -> io::Result<()> {
+        // `Display` never uses an exponent
+        writer.write_all(fixed_point(value.to_string()).as_bytes())
+    }
+
+    fn write_f64<W: ?Sized + io::Write>(&mut self, writer: &mut W, value: f64) -> io::Result<()> {
+        writer.write_all(fixed_point(value.to_string()).as_bytes())
+    }
+}
+
+// `FIXED_POINT_NUMBERS=false` keeps serde_json's own notation
+pub fn to_vec<T: Serialize + ?Sized>(value: &T, fixed_point: bool) -> serde_json::Result<Vec<u8>> {
+    if !fixed_point {
+        return serde_json::to_vec(value);
+    }
+    let mut bytes = Vec::new();
+    value.serialize(&mut Serializer::with_formatter(&mut bytes, FixedPointFormatter))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn fixed(value: serde_json::Value) -> String {
+        String::from_utf8(to_vec(&value, true).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn small_and_large_values_have_no_exponent() {
+        assert_eq!(fixed(json!(0.0000012)), "0.0000012");
+        assert_eq!(fixed(json!(-0.0000012)), "-0.0000012");
+        assert_eq!(fixed(json!(1e16)), "10000000000000000.0");
+        assert_eq!(fixed(json!(f32::MIN_POSITIVE as f64)), format!("{}", f32::MIN_POSITIVE as f64));
+    }
+
+    #[test]
+    fn zero_and_integral_floats_keep_a_decimal_point() {
+        assert_eq!(fixed(json!(0.0)), "0.0");
+        assert_eq!(fixed(json!(-0.0)), "0.0");
+        assert_eq!(fixed(json!(80.0)), "80.0");
+        assert_eq!(fixed(json!(-2.0)), "-2.0");
+    }
+
+    #[test]
+    fn ordinary_values_match_serde_json() {
+        let value = json!({ "wheight_kg": 80.25, "imc": 24.7, "delta": -0.13, "count": 3, "note": null, "ok": true });
+        assert_eq!(fixed(value.clone()), serde_json::to_string(&value).unwrap());
+    }
+
+    #[test]
+    fn f32_fields_are_written_the_same_way() {
+        #[derive(Serialize)]
+        struct Output {
+            tiny: f32,
+            negative_zero: f32
+        }
+        let bytes = to_vec(&Output { tiny: 0.0000005, negative_zero: -0.0 }, true).unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), r#"{"tiny":0.0000005,"negative_zero":0.0}"#);
+    }
+
+    #[test]
+    fn disabled_is_plain_serde_json() {
+        assert_eq!(String::from_utf8(to_vec(&json!(0.0000012), false).unwrap()).unwrap(), "1.2e-6");
+    }
+}
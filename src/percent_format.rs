@@ -0,0 +1,48 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use serde::Deserialize;
+
+use crate::error::AppError;
+
+// how a body's percentages are written. Nothing is guessed from the values themselves: a
+// `0.9` could be a fraction of 90% or a genuine 0.9%, so a client sending fractions has to say
+// so with `?percent_format=fraction`, and anything unflagged is taken as a percent
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PercentFormat {
+    // `0.23` for 23%
+    Fraction,
+    // `23.0` for 23%
+    #[default]
+    Percent
+}
+
+#[derive(Deserialize)]
+struct PercentFormatQuery {
+    #[serde(default)]
+    percent_format: PercentFormat
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for PercentFormat {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<PercentFormatQuery>::from_request_parts(parts, state).await
+            .map_err(|_| AppError::BadRequest("percent_format must be 'fraction' or 'percent'".to_string()))?;
+        Ok(query.percent_format)
+    }
+}
+
+impl PercentFormat {
+    // what the body's percentages are multiplied by to store them as percents
+    pub fn factor(self) -> f32 {
+        match self {
+            PercentFormat::Fraction => 100_f32,
+            PercentFormat::Percent => 1_f32
+        }
+    }
+}
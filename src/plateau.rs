@@ -0,0 +1,131 @@
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::AppError, daily_values::Collapse, trend::{days_since, linear_fit, wheight_series, STABLE_KG_PER_WEEK},
+    units::{OutputUnit, Unit}, AppState,
+};
+
+const DEFAULT_DAYS: i64 = 90;
+// every start point is fitted separately, so the series is kept to a couple of years
+const MAX_DAYS: i64 = 730;
+// three weeks without movement is when a plan is worth revisiting
+const DEFAULT_MIN_DAYS: i64 = 21;
+// two points always sit on a line of their own, however noisy
+const MIN_POINTS: usize = 3;
+
+#[derive(Deserialize)]
+pub struct PlateauQuery {
+    // how far back the series goes
+    days: Option<i64>,
+    // how long the stretch has to last to count as a plateau
+    min_days: Option<i64>,
+    // slower than this either way is flat, `STABLE_KG_PER_WEEK` by default
+    threshold_kg_per_week: Option<f64>
+}
+
+#[derive(Serialize)]
+struct PlateauOutput {
+    plateaued: bool,
+    // first measurement of the flat stretch ending at the latest one, null when even the
+    // last few measurements move faster than the threshold; shorter than `min_days` while
+    // `plateaued` is false
+    start_date: Option<DateTime<Utc>>,
+    duration_days: Option<i64>,
+    slope_kg_per_week: Option<f32>,
+    based_on_count: usize,
+    min_days: i64,
+    threshold_kg_per_week: f64,
+    unit: Unit
+}
+
+// `(start, kg per week, points)` of the longest stretch ending at the last point whose fitted
+// line is slower than `threshold`, the earliest start that still fits a flat line
+fn flat_stretch(points: &[(DateTime<Utc>, f64)], threshold: f64) -> Option<(DateTime<Utc>, f64, usize)> {
+    (0..points.len().saturating_sub(MIN_POINTS - 1)).find_map(|start| {
+        let (origin, _) = points[start];
+        let xy: Vec<(f64, f64)> = points[start..].iter().map(|(date, kg)| (days_since(origin, *date), *kg)).collect();
+        let kg_per_week = linear_fit(&xy)?.slope * 7_f64;
+        (kg_per_week.abs() < threshold).then_some((origin, kg_per_week, xy.len()))
+    })
+}
+
+// whether the weight has stopped moving: the longest stretch ending at the latest measurement
+// whose fitted line is slower than the threshold, a plateau once it lasts `min_days`
+pub async fn get_plateau(State(state): State<AppState>, Query(query): Query<PlateauQuery>, collapse: Collapse, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let days = query.days.unwrap_or(DEFAULT_DAYS);
+    if !(1..=MAX_DAYS).contains(&days) {
+        return Err(AppError::BadRequest(format!("days must be between 1 and {}", MAX_DAYS)));
+    }
+    let min_days = query.min_days.unwrap_or(DEFAULT_MIN_DAYS);
+    if !(1..=days).contains(&min_days) {
+        return Err(AppError::BadRequest(format!("min_days must be between 1 and days ({})", days)));
+    }
+    let threshold = query.threshold_kg_per_week.unwrap_or(STABLE_KG_PER_WEEK);
+    if !threshold.is_finite() || threshold <= 0_f64 {
+        return Err(AppError::BadRequest("threshold_kg_per_week must be greater than zero".to_string()));
+    }
+
+    let points = wheight_series(&state, Utc::now() - Duration::days(days), collapse.requested()).await?;
+    let Some(&(last, _)) = points.last() else {
+        return Err(AppError::NotFound);
+    };
+    let stretch = flat_stretch(&points, threshold);
+
+    let duration_days = stretch.map(|(start, _, _)| (last - start).num_days());
+    Ok((StatusCode::OK, Json(PlateauOutput {
+        plateaued: duration_days.is_some_and(|d| d >= min_days),
+        start_date: stretch.map(|(start, _, _)| start),
+        duration_days,
+        slope_kg_per_week: stretch.map(|(_, slope, _)| unit.convert_kg(slope as f32)),
+        based_on_count: stretch.map_or(0, |(_, _, count)| count),
+        min_days,
+        threshold_kg_per_week: threshold,
+        unit
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    // one point per day from 2024-01-01
+    fn daily(weights: &[f64]) -> Vec<(DateTime<Utc>, f64)> {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 7, 0, 0).unwrap();
+        weights.iter().enumerate().map(|(i, kg)| (start + Duration::days(i as i64), *kg)).collect()
+    }
+
+    #[test]
+    fn stretch_starts_where_the_series_levels_off() {
+        // a kilo a day down, then flat for the last five days
+        let points = daily(&[85.0, 84.0, 83.0, 82.0, 81.0, 80.0, 80.0, 80.0, 80.0, 80.0]);
+        let (start, kg_per_week, count) = flat_stretch(&points, STABLE_KG_PER_WEEK).unwrap();
+        assert_eq!(start, points[5].0);
+        assert_eq!(kg_per_week, 0.0);
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn a_constant_series_is_flat_from_its_first_point() {
+        let points = daily(&[80.0; 6]);
+        assert_eq!(flat_stretch(&points, STABLE_KG_PER_WEEK).map(|(start, _, count)| (start, count)), Some((points[0].0, 6)));
+    }
+
+    #[test]
+    fn no_stretch_below_three_points_or_while_moving() {
+        assert!(flat_stretch(&[], STABLE_KG_PER_WEEK).is_none());
+        assert!(flat_stretch(&daily(&[80.0, 80.0]), STABLE_KG_PER_WEEK).is_none());
+        assert!(flat_stretch(&daily(&[84.0, 83.0, 82.0, 81.0]), STABLE_KG_PER_WEEK).is_none());
+        // the same fall is flat enough for a generous threshold
+        assert!(flat_stretch(&daily(&[84.0, 83.0, 82.0, 81.0]), 7.5).is_some());
+    }
+
+    #[test]
+    fn points_at_the_same_moment_fit_no_line() {
+        let at = Utc.with_ymd_and_hms(2024, 1, 1, 7, 0, 0).unwrap();
+        assert!(flat_stretch(&[(at, 80.0), (at, 80.0), (at, 80.5)], STABLE_KG_PER_WEEK).is_none());
+    }
+}
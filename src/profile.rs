@@ -0,0 +1,199 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use mongodb::{bson::{self, doc}, options::{FindOneAndUpdateOptions, FindOneOptions, ReturnDocument}};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::AppError, find_by_date, goal::GoalEntity, noise::NoiseThresholds, target::TargetBand, units::Unit, validation::{Validate, ValidatedJson}, AppState};
+
+// the service tracks a single person, so there is exactly one profile document
+pub const PROFILE_ID: &str = "default";
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ProfileEntity {
+    #[serde(default)]
+    pub units: Unit,
+    #[serde(default)]
+    pub birth_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub target_band: Option<TargetBand>,
+    #[serde(default)]
+    pub goal: Option<GoalEntity>,
+    #[serde(default)]
+    pub height_cm: Option<f32>,
+    #[serde(default)]
+    pub noise_thresholds: NoiseThresholds
+}
+
+#[derive(Deserialize)]
+pub struct UnitsInput {
+    units: Unit
+}
+
+impl Validate for UnitsInput {}
+
+#[derive(Deserialize)]
+pub struct BirthDateInput {
+    birth_date: Option<NaiveDate>
+}
+
+impl Validate for BirthDateInput {
+    fn validate(&self) -> Result<(), AppError> {
+        if self.birth_date.is_some_and(|d| d > chrono::Utc::now().date_naive()) {
+            return Err(AppError::Validation("birth_date can't be in the future".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TargetBandInput {
+    target_band: Option<TargetBand>
+}
+
+impl Validate for TargetBandInput {
+    fn validate(&self) -> Result<(), AppError> {
+        match self.target_band.and_then(|band| band.problem()) {
+            Some(problem) => Err(AppError::Validation(problem)),
+            None => Ok(())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct HeightInput {
+    height_cm: Option<f32>
+}
+
+impl Validate for HeightInput {
+    fn validate(&self) -> Result<(), AppError> {
+        if self.height_cm.is_some_and(|h| !(50_f32..=272_f32).contains(&h)) {
+            return Err(AppError::Validation("height_cm must be between 50 and 272".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NoiseThresholdsInput {
+    #[serde(default)]
+    noise_thresholds: NoiseThresholds
+}
+
+impl Validate for NoiseThresholdsInput {
+    fn validate(&self) -> Result<(), AppError> {
+        match self.noise_thresholds.problem() {
+            Some(problem) => Err(AppError::Validation(problem)),
+            None => Ok(())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct GoalInput {
+    target_wheight_kg: f32,
+    target_date: NaiveDate,
+    // where the plan starts from, the latest measurement when absent
+    start_wheight_kg: Option<f32>
+}
+
+#[derive(Deserialize)]
+pub struct GoalUpdateInput {
+    goal: Option<GoalInput>
+}
+
+impl Validate for GoalUpdateInput {
+    fn validate(&self) -> Result<(), AppError> {
+        let Some(goal) = &self.goal else {
+            return Ok(());
+        };
+        let weights = [Some(goal.target_wheight_kg), goal.start_wheight_kg];
+        if weights.iter().flatten().any(|w| !w.is_finite() || *w <= 0_f32) {
+            return Err(AppError::Validation("target_wheight_kg and start_wheight_kg must be positive".to_string()));
+        }
+        if goal.target_date <= chrono::Utc::now().date_naive() {
+            return Err(AppError::Validation("target_date must be in the future".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl AppState {
+    // the profile, or the defaults when none was saved yet
+    pub async fn profile(&self) -> Result<ProfileEntity, AppError> {
+        if let Some(profile) = &*self.profile.read().await {
+            return Ok(profile.clone());
+        }
+        let filter = doc! { "_id": PROFILE_ID };
+        let profile = self.timed("find_one", &filter, self.profiles.find_one(filter.clone(), self.bounded(FindOneOptions::default()))).await?.unwrap_or_default();
+        *self.profile.write().await = Some(profile.clone());
+        Ok(profile)
+    }
+
+    // the updated profile comes back from the primary, a secondary might not have the write yet
+    async fn update_profile(&self, set: bson::Document) -> Result<ProfileEntity, AppError> {
+        let options = FindOneAndUpdateOptions::builder().upsert(true).return_document(ReturnDocument::After).build();
+        let filter = doc! { "_id": PROFILE_ID };
+        let profile = self.timed("find_one_and_update", &filter, self.profiles.find_one_and_update(filter.clone(), doc! { "$set": set }, options)).await?.unwrap_or_default();
+        *self.profile.write().await = Some(profile.clone());
+        Ok(profile)
+    }
+}
+
+pub async fn get_profile(State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
+    Ok((StatusCode::OK, Json(state.profile().await?)))
+}
+
+// preferred unit for reads that don't pass `?unit=`
+pub async fn put_units(State(state): State<AppState>, ValidatedJson(payload): ValidatedJson<UnitsInput>) -> Result<impl IntoResponse, AppError> {
+    let profile = state.update_profile(doc! { "units": bson::to_bson(&payload.units)? }).await?;
+    Ok((StatusCode::OK, Json(profile)))
+}
+
+// lets outputs report the chronological age next to the metabolic one; null clears it
+pub async fn put_birth_date(State(state): State<AppState>, ValidatedJson(payload): ValidatedJson<BirthDateInput>) -> Result<impl IntoResponse, AppError> {
+    let profile = state.update_profile(doc! { "birth_date": bson::to_bson(&payload.birth_date)? }).await?;
+    Ok((StatusCode::OK, Json(profile)))
+}
+
+// maintenance range the latest measurement reports its `target_status` against; null clears it
+pub async fn put_target_band(State(state): State<AppState>, ValidatedJson(payload): ValidatedJson<TargetBandInput>) -> Result<impl IntoResponse, AppError> {
+    let profile = state.update_profile(doc! { "target_band": bson::to_bson(&payload.target_band)? }).await?;
+    Ok((StatusCode::OK, Json(profile)))
+}
+
+// what `imc` is computed from; null clears it
+pub async fn put_height(State(state): State<AppState>, ValidatedJson(payload): ValidatedJson<HeightInput>) -> Result<impl IntoResponse, AppError> {
+    let profile = state.update_profile(doc! { "height_cm": bson::to_bson(&payload.height_cm)? }).await?;
+    Ok((StatusCode::OK, Json(profile)))
+}
+
+// replaces the thresholds below which a `_diff` is reported as 0, those left out (or null)
+// fall back to the `NOISE_THRESHOLD_*` environment
+pub async fn put_noise_thresholds(State(state): State<AppState>, ValidatedJson(payload): ValidatedJson<NoiseThresholdsInput>) -> Result<impl IntoResponse, AppError> {
+    let profile = state.update_profile(doc! { "noise_thresholds": bson::to_bson(&payload.noise_thresholds)? }).await?;
+    Ok((StatusCode::OK, Json(profile)))
+}
+
+// starts a new goal from today, replacing the active one; null clears it
+pub async fn put_goal(State(state): State<AppState>, ValidatedJson(payload): ValidatedJson<GoalUpdateInput>) -> Result<impl IntoResponse, AppError> {
+    let goal = match payload.goal {
+        Some(input) => {
+            let start_wheight_kg = match input.start_wheight_kg {
+                Some(start) => start,
+                None => match find_by_date(&state, -1).await? {
+                    Some(latest) => latest.wheight_kg,
+                    None => return Err(AppError::Validation("start_wheight_kg is required until there is a measurement".to_string()))
+                }
+            };
+            Some(GoalEntity {
+                start_wheight_kg,
+                target_wheight_kg: input.target_wheight_kg,
+                created_on: chrono::Utc::now().date_naive(),
+                target_date: input.target_date
+            })
+        },
+        None => None
+    };
+    let profile = state.update_profile(doc! { "goal": bson::to_bson(&goal)? }).await?;
+    Ok((StatusCode::OK, Json(profile)))
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, NaiveDate, Utc};
+use mongodb::{bson::doc, options::FindOptions};
+use serde::Serialize;
+
+use crate::{error::AppError, units::{OutputUnit, Unit}, AppState, WheightMeasurementOutput};
+
+// neighbours on each side a weight is compared against
+const OUTLIER_WINDOW: usize = 3;
+// how far from the neighbours' median a weight may be before it's an outlier
+const OUTLIER_THRESHOLD_KG: f32 = 2.5;
+
+fn median(values: &mut [f32]) -> f32 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) { (values[mid - 1] + values[mid]) / 2_f32 } else { values[mid] }
+}
+
+// for each weight (in date order) whether it is far from the median of its neighbours, `None`
+// when it has none to compare with; a median keeps a single bad reading from dragging its own
+// reference along with it
+pub fn outlier_flags(weights: &[f32]) -> Vec<Option<bool>> {
+    (0..weights.len())
+        .map(|i| {
+            let (start, end) = (i.saturating_sub(OUTLIER_WINDOW), weights.len().min(i + OUTLIER_WINDOW + 1));
+            let mut neighbours: Vec<f32> = (start..end).filter(|&j| j != i).map(|j| weights[j]).collect();
+            (!neighbours.is_empty()).then(|| (weights[i] - median(&mut neighbours)).abs() > OUTLIER_THRESHOLD_KG)
+        })
+        .collect()
+}
+
+pub fn outlier_indices(weights: &[f32]) -> Vec<usize> {
+    outlier_flags(weights).into_iter().enumerate().filter(|(_, flag)| *flag == Some(true)).map(|(i, _)| i).collect()
+}
+
+// why a measurement can't be physically right, if it can't
+pub fn implausible_reason(m: &WheightMeasurementOutput) -> Option<String> {
+    if m.wheight_kg <= 0_f32 {
+        return Some(format!("wheight_kg must be positive, got {}", m.wheight_kg));
+    }
+    let percentages = [
+        ("fat_percentage", m.fat_percentage),
+        ("water_percentage", m.water_percentage),
+        ("protein_percentage", m.protein_percentage),
+    ];
+    if let Some((name, value)) = percentages.iter().find(|(_, v)| !(0_f32..=100_f32).contains(v)) {
+        return Some(format!("{} out of range: {}", name, value));
+    }
+    let sum: f32 = percentages.iter().map(|(_, v)| v).sum();
+    if sum > 100_f32 {
+        return Some(format!("fat, water and protein percentages add up to {:.1}%", sum));
+    }
+    let components = m.muscle_kg + m.fat_kg + m.bone_kg;
+    if components > m.wheight_kg {
+        return Some(format!("muscle, fat and bone add up to {:.1} kg, more than the {:.1} kg total", components, m.wheight_kg));
+    }
+    None
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Quality {
+    Low,
+    Medium,
+    High
+}
+
+// missing body metrics from which a record counts as poorly populated
+const MANY_MISSING_METRICS: usize = 3;
+
+// what a record's quality is judged on
+#[derive(Clone, Copy)]
+pub struct Signals {
+    pub implausible: bool,
+    // against the neighbouring weights, `None` where they aren't known
+    pub outlier: Option<bool>,
+    pub missing_metrics: usize
+}
+
+// `low` for implausible, outlying or poorly populated records, `high` for complete ones known
+// to sit with their neighbours, `medium` otherwise
+pub fn score(signals: Signals) -> Quality {
+    if signals.implausible || signals.outlier == Some(true) || signals.missing_metrics >= MANY_MISSING_METRICS {
+        Quality::Low
+    } else if signals.outlier == Some(false) && signals.missing_metrics == 0 {
+        Quality::High
+    } else {
+        Quality::Medium
+    }
+}
+
+// scales that don't report a metric store it as 0
+fn missing_metrics(m: &WheightMeasurementOutput) -> usize {
+    let metrics = [
+        m.imc, m.fat_percentage, m.water_percentage, m.protein_percentage, m.metabolism_kcal,
+        m.visceral_fat_index, m.muscle_kg, m.bone_kg, m.metabolic_age as f32,
+    ];
+    metrics.iter().filter(|v| **v == 0_f32).count()
+}
+
+// `outlier` comes from `outlier_flags` where the neighbours were read, a record on its own can
+// be at most `medium`
+pub fn assess(m: &WheightMeasurementOutput, outlier: Option<bool>) -> Quality {
+    score(Signals { implausible: implausible_reason(m).is_some(), outlier, missing_metrics: missing_metrics(m) })
+}
+
+// calendar days (UTC) with more than one measurement
+pub fn duplicate_days(dates: &[DateTime<Utc>]) -> Vec<NaiveDate> {
+    let mut counts: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+    for date in dates {
+        *counts.entry(date.date_naive()).or_default() += 1;
+    }
+    counts.into_iter().filter(|(_, n)| *n > 1).map(|(day, _)| day).collect()
+}
+
+#[derive(Serialize)]
+struct AnomalyOutput {
+    id: String,
+    date: DateTime<Utc>,
+    wheight_kg: f32,
+    unit: Unit,
+    reason: String
+}
+
+// everything that looks wrong in the history, one entry per record and reason
+pub async fn get_anomalies(State(state): State<AppState>, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let options = FindOptions::builder().sort(doc! { "date": 1 }).build();
+    let mut cursor = state.timed("find", "all", state.collection.find(None, state.bounded(options))).await?;
+    let mut measurements = Vec::new();
+    while cursor.advance().await? {
+        measurements.push(WheightMeasurementOutput::from_entity(cursor.deserialize_current()?));
+    }
+
+    let anomaly = |m: &WheightMeasurementOutput, reason: String| AnomalyOutput {
+        id: m.id.clone(), date: m.date, wheight_kg: unit.convert_kg(m.wheight_kg), unit, reason
+    };
+    let mut anomalies = Vec::new();
+
+    let weights: Vec<f32> = measurements.iter().map(|m| m.wheight_kg).collect();
+    for i in outlier_indices(&weights) {
+        anomalies.push(anomaly(&measurements[i], format!("outlier: more than {} kg from its neighbours", OUTLIER_THRESHOLD_KG)));
+    }
+    for m in &measurements {
+        if let Some(reason) = implausible_reason(m) {
+            anomalies.push(anomaly(m, format!("implausible: {}", reason)));
+        }
+    }
+    let dates: Vec<DateTime<Utc>> = measurements.iter().map(|m| m.date).collect();
+    for day in duplicate_days(&dates) {
+        for m in measurements.iter().filter(|m| m.date.date_naive() == day) {
+            anomalies.push(anomaly(m, format!("duplicate: more than one measurement on {}", day)));
+        }
+    }
+
+    anomalies.sort_by_key(|a| a.date);
+    Ok((StatusCode::OK, Json(anomalies)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signals(implausible: bool, outlier: Option<bool>, missing_metrics: usize) -> Signals {
+        Signals { implausible, outlier, missing_metrics }
+    }
+
+    #[test]
+    fn score_weighs_every_signal() {
+        let cases = [
+            (signals(false, Some(false), 0), Quality::High),
+            (signals(false, Some(false), 1), Quality::Medium),
+            (signals(false, None, 0), Quality::Medium),
+            (signals(false, Some(false), MANY_MISSING_METRICS), Quality::Low),
+            (signals(false, Some(true), 0), Quality::Low),
+            (signals(true, Some(false), 0), Quality::Low),
+            (signals(true, None, 0), Quality::Low),
+        ];
+        for (given, expected) in cases {
+            assert_eq!(score(given), expected, "implausible {}, outlier {:?}, {} missing", given.implausible, given.outlier, given.missing_metrics);
+        }
+    }
+
+    #[test]
+    fn outlier_flags_need_neighbours() {
+        assert_eq!(outlier_flags(&[80_f32]), vec![None]);
+        assert_eq!(outlier_flags(&[80_f32, 80.4, 86.0, 80.2, 79.9]), vec![Some(false), Some(false), Some(true), Some(false), Some(false)]);
+    }
+}
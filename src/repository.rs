@@ -0,0 +1,174 @@
+use axum::async_trait;
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, DateTime as BsonDateTime, Document},
+    options::{FindOneAndDeleteOptions, FindOneAndUpdateOptions, FindOneOptions, ReturnDocument},
+    Collection,
+};
+
+use crate::{audit::AuditEntity, AppState, WheightMeasurementEntity};
+
+// the single-measurement reads and writes and their audit trail, behind a trait so handler
+// tests can run against an in-memory fake instead of a database
+#[async_trait]
+pub trait MeasurementRepository {
+    async fn find_by_id(&self, state: &AppState, id: ObjectId) -> mongodb::error::Result<Option<WheightMeasurementEntity>>;
+    async fn insert(&self, state: &AppState, measurement: &WheightMeasurementEntity) -> mongodb::error::Result<()>;
+    // any measurement other than `except` dated between `from` and `to`, both included
+    async fn find_between(&self, state: &AppState, from: BsonDateTime, to: BsonDateTime, except: Option<ObjectId>) -> mongodb::error::Result<Option<WheightMeasurementEntity>>;
+    // `set` applied and the version bumped, returning the measurement before and after; `None`
+    // when there was none with that id (at `version`, if given) or it is locked
+    async fn update(&self, state: &AppState, id: ObjectId, version: Option<u32>, set: Document) -> mongodb::error::Result<Option<(WheightMeasurementEntity, WheightMeasurementEntity)>>;
+    // sets the flag `field` (`is_favorite` or `is_locked`) to `value`, bumping the version, and
+    // returns the measurement before and after; `None` when there was none with that id, it
+    // already had `value`, or `unless_locked` and it is locked
+    async fn set_flag(&self, state: &AppState, id: ObjectId, field: &'static str, value: bool, unless_locked: bool) -> mongodb::error::Result<Option<(WheightMeasurementEntity, WheightMeasurementEntity)>>;
+    // the deleted measurement, `None` when there was none with that id or it is locked
+    async fn delete(&self, state: &AppState, id: ObjectId) -> mongodb::error::Result<Option<WheightMeasurementEntity>>;
+    async fn record_audit(&self, state: &AppState, entries: Vec<AuditEntity>) -> mongodb::error::Result<()>;
+    // the audit entry of the measurement's deletion, if it was deleted
+    async fn find_deletion(&self, state: &AppState, id: ObjectId) -> mongodb::error::Result<Option<AuditEntity>>;
+}
+
+// what an update writes over `before`, rebuilt rather than read back
+fn updated(before: &WheightMeasurementEntity, set: &Document) -> mongodb::error::Result<WheightMeasurementEntity> {
+    let mut document = bson::to_document(before)?;
+    document.extend(set.clone());
+    let mut after: WheightMeasurementEntity = bson::from_document(document)?;
+    after.version = before.version + 1;
+    Ok(after)
+}
+
+#[async_trait]
+impl MeasurementRepository for Collection<WheightMeasurementEntity> {
+    async fn find_by_id(&self, state: &AppState, id: ObjectId) -> mongodb::error::Result<Option<WheightMeasurementEntity>> {
+        let filter = doc! { "_id": id };
+        state.timed("find_one", &filter, self.find_one(filter.clone(), state.bounded(FindOneOptions::default()))).await
+    }
+
+    async fn insert(&self, state: &AppState, measurement: &WheightMeasurementEntity) -> mongodb::error::Result<()> {
+        state.timed("insert_one", &measurement._id.to_hex(), self.insert_one(measurement, None)).await?;
+        Ok(())
+    }
+
+    async fn find_between(&self, state: &AppState, from: BsonDateTime, to: BsonDateTime, except: Option<ObjectId>) -> mongodb::error::Result<Option<WheightMeasurementEntity>> {
+        let mut filter = doc! { "date": { "$gte": from, "$lte": to } };
+        if let Some(except) = except {
+            filter.insert("_id", doc! { "$ne": except });
+        }
+        // a secondary lagging behind would let a duplicate through
+        state.timed("find_one", &filter, self.find_one(filter.clone(), state.bounded_primary(FindOneOptions::default()))).await
+    }
+
+    async fn update(&self, state: &AppState, id: ObjectId, version: Option<u32>, set: Document) -> mongodb::error::Result<Option<(WheightMeasurementEntity, WheightMeasurementEntity)>> {
+        let mut filter = doc! { "_id": id, "is_locked": { "$ne": true } };
+        match version {
+            // documents written before versioning have no `version` field
+            Some(0) => { filter.insert("version", doc! { "$in": [0, null] }); },
+            Some(v) => { filter.insert("version", v); },
+            None => {}
+        }
+        let update = doc! { "$set": set.clone(), "$inc": { "version": 1 } };
+        let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::Before).build();
+        let Some(before) = state.timed("find_one_and_update", &filter, self.find_one_and_update(filter.clone(), update, options)).await? else {
+            return Ok(None);
+        };
+        let after = updated(&before, &set)?;
+        Ok(Some((before, after)))
+    }
+
+    async fn set_flag(&self, state: &AppState, id: ObjectId, field: &'static str, value: bool, unless_locked: bool) -> mongodb::error::Result<Option<(WheightMeasurementEntity, WheightMeasurementEntity)>> {
+        let mut filter = doc! { "_id": id, field: { "$ne": value } };
+        if unless_locked {
+            filter.insert("is_locked", doc! { "$ne": true });
+        }
+        let set = doc! { field: value, "updated_at": BsonDateTime::now() };
+        let update = doc! { "$set": set.clone(), "$inc": { "version": 1 } };
+        let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::Before).build();
+        let Some(before) = state.timed("find_one_and_update", &filter, self.find_one_and_update(filter.clone(), update, options)).await? else {
+            return Ok(None);
+        };
+        let after = updated(&before, &set)?;
+        Ok(Some((before, after)))
+    }
+
+    async fn delete(&self, state: &AppState, id: ObjectId) -> mongodb::error::Result<Option<WheightMeasurementEntity>> {
+        let filter = doc! { "_id": id, "is_locked": { "$ne": true } };
+        state.timed("find_one_and_delete", &filter, self.find_one_and_delete(filter.clone(), FindOneAndDeleteOptions::default())).await
+    }
+
+    async fn record_audit(&self, state: &AppState, entries: Vec<AuditEntity>) -> mongodb::error::Result<()> {
+        state.timed("insert_many", &entries.len(), state.audit.insert_many(entries, None)).await?;
+        Ok(())
+    }
+
+    async fn find_deletion(&self, state: &AppState, id: ObjectId) -> mongodb::error::Result<Option<AuditEntity>> {
+        let filter = doc! { "measurement_id": id, "operation": "delete" };
+        state.timed("find_one", &filter, state.audit.find_one(filter.clone(), state.bounded_primary(FindOneOptions::default()))).await
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    pub struct InMemoryMeasurements {
+        pub measurements: Mutex<Vec<WheightMeasurementEntity>>,
+        pub audit: Mutex<Vec<AuditEntity>>
+    }
+
+    #[async_trait]
+    impl MeasurementRepository for InMemoryMeasurements {
+        async fn find_by_id(&self, _: &AppState, id: ObjectId) -> mongodb::error::Result<Option<WheightMeasurementEntity>> {
+            Ok(self.measurements.lock().unwrap().iter().find(|m| m._id == id).cloned())
+        }
+
+        async fn insert(&self, _: &AppState, measurement: &WheightMeasurementEntity) -> mongodb::error::Result<()> {
+            self.measurements.lock().unwrap().push(measurement.clone());
+            Ok(())
+        }
+
+        async fn find_between(&self, _: &AppState, from: BsonDateTime, to: BsonDateTime, except: Option<ObjectId>) -> mongodb::error::Result<Option<WheightMeasurementEntity>> {
+            Ok(self.measurements.lock().unwrap().iter().find(|m| m.date >= from && m.date <= to && Some(m._id) != except).cloned())
+        }
+
+        async fn update(&self, _: &AppState, id: ObjectId, version: Option<u32>, set: Document) -> mongodb::error::Result<Option<(WheightMeasurementEntity, WheightMeasurementEntity)>> {
+            let mut measurements = self.measurements.lock().unwrap();
+            let Some(m) = measurements.iter_mut().find(|m| m._id == id && !m.is_locked && version.is_none_or(|v| m.version == v)) else {
+                return Ok(None);
+            };
+            let after = updated(m, &set)?;
+            Ok(Some((std::mem::replace(m, after.clone()), after)))
+        }
+
+        async fn set_flag(&self, _: &AppState, id: ObjectId, field: &'static str, value: bool, unless_locked: bool) -> mongodb::error::Result<Option<(WheightMeasurementEntity, WheightMeasurementEntity)>> {
+            let mut measurements = self.measurements.lock().unwrap();
+            let Some(m) = measurements.iter_mut().find(|m| m._id == id && !(unless_locked && m.is_locked)) else {
+                return Ok(None);
+            };
+            if bson::to_document(&*m)?.get_bool(field).unwrap_or(false) == value {
+                return Ok(None);
+            }
+            let after = updated(m, &doc! { field: value, "updated_at": BsonDateTime::now() })?;
+            Ok(Some((std::mem::replace(m, after.clone()), after)))
+        }
+
+        async fn delete(&self, _: &AppState, id: ObjectId) -> mongodb::error::Result<Option<WheightMeasurementEntity>> {
+            let mut measurements = self.measurements.lock().unwrap();
+            Ok(measurements.iter().position(|m| m._id == id && !m.is_locked).map(|i| measurements.remove(i)))
+        }
+
+        async fn record_audit(&self, _: &AppState, entries: Vec<AuditEntity>) -> mongodb::error::Result<()> {
+            self.audit.lock().unwrap().extend(entries);
+            Ok(())
+        }
+
+        async fn find_deletion(&self, _: &AppState, id: ObjectId) -> mongodb::error::Result<Option<AuditEntity>> {
+            let is_deletion = |e: &AuditEntity| mongodb::bson::to_document(e)
+                .is_ok_and(|d| d.get_object_id("measurement_id") == Ok(id) && d.get_str("operation") == Ok("delete"));
+            Ok(self.audit.lock().unwrap().iter().find(|e| is_deletion(e)).cloned())
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use axum::{extract::{Path, State}, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, DateTime as BsonDateTime};
+use serde::Deserialize;
+
+use crate::{
+    aggregates, audit::{AuditEntity, AuditOperation}, error::AppError, lock, parse_object_id, reject_duplicate,
+    validation::{Validate, ValidatedJson}, AppState, WheightMeasurementOutput,
+};
+
+#[derive(Deserialize)]
+pub struct RescheduleInput {
+    date: DateTime<Utc>
+}
+
+impl Validate for RescheduleInput {}
+
+// moves a measurement entered with the wrong date, leaving everything else as it was; the new
+// date has to pass the same duplicate check as a create, and an exact collision always fails
+pub async fn reschedule_measurement(State(state): State<AppState>, Path(id): Path<String>, ValidatedJson(payload): ValidatedJson<RescheduleInput>) -> Result<impl IntoResponse, AppError> {
+    let oid = parse_object_id(&id)?;
+    // a locked one is refused whatever its new date would collide with
+    match state.measurements.find_by_id(&state, oid).await? {
+        Some(m) if m.is_locked => return Err(lock::locked()),
+        Some(_) => {},
+        None => return Err(AppError::NotFound)
+    }
+    // like the create's, the check and the update aren't atomic
+    reject_duplicate(&state, payload.date, Some(oid)).await?;
+
+    let set = doc! { "date": BsonDateTime::from_chrono(payload.date), "updated_at": BsonDateTime::now() };
+    let Some((before, after)) = state.measurements.update(&state, oid, None, set).await? else {
+        return Err(match state.measurements.find_by_id(&state, oid).await? {
+            Some(_) => lock::locked(),
+            None => AppError::NotFound
+        });
+    };
+    state.audit(vec![AuditEntity::new(AuditOperation::Update, Some(&before), Some(&after))]).await;
+    state.invalidate_latest(&id, payload.date).await;
+    aggregates::refresh_days(&state, &[before.date.to_chrono().date_naive(), payload.date.date_naive()]).await;
+    Ok((StatusCode::OK, Json(WheightMeasurementOutput::from_entity(after))))
+}
@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use mongodb::{bson::{doc, DateTime as BsonDateTime, Document}, options::FindOptions};
+
+use crate::{aggregates, error::AppError, AppState};
+
+// every `interval`, deletes the measurements (and their daily aggregates) dated more than
+// `retention_days` ago, except locked ones; only spawned when a retention is configured
+pub async fn run(state: AppState, retention_days: u32, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match purge(&state, retention_days).await {
+            Ok(purged) => tracing::info!("Retention purged {} measurements older than {} days", purged, retention_days),
+            Err(e) => tracing::warn!("Error purging old measurements: {:?}", e)
+        }
+    }
+}
+
+async fn purge(state: &AppState, retention_days: u32) -> Result<u64, AppError> {
+    let cutoff = Utc::now() - chrono::Duration::days(retention_days as i64);
+    let old = doc! { "date": { "$lt": BsonDateTime::from_chrono(cutoff) } };
+    let mut unlocked = old.clone();
+    unlocked.insert("is_locked", doc! { "$ne": true });
+    let result = state.timed("delete_many", &unlocked, state.collection.delete_many(unlocked.clone(), None)).await?;
+    if result.deleted_count == 0 {
+        return Ok(0);
+    }
+
+    let cutoff_day = cutoff.date_naive();
+    let filter = doc! { "date": { "$lt": BsonDateTime::from_chrono(aggregates::day_start(cutoff_day)) } };
+    state.timed("delete_many", &filter, state.aggregates.delete_many(filter.clone(), None)).await?;
+    // the cutoff falls inside a day, part of which is gone now, and the days of the locked
+    // measurements that stayed still have one
+    let mut days = vec![cutoff_day];
+    let options = FindOptions::builder().projection(doc! { "date": 1 }).build();
+    let mut cursor = state.timed("find", &old, state.collection.clone_with_type::<Document>().find(old.clone(), state.bounded_primary(options))).await?;
+    while cursor.advance().await? {
+        if let Ok(date) = cursor.deserialize_current()?.get_datetime("date") {
+            days.push(date.to_chrono().date_naive());
+        }
+    }
+    aggregates::refresh_days(state, &days).await;
+    *state.latest.write().await = None;
+    Ok(result.deleted_count)
+}
@@ -0,0 +1,81 @@
+use axum::{
+    body::{self, Body},
+    http::{header, Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Number, Value};
+
+use crate::{error::AppError, number_format};
+
+const DEFAULT_DECIMALS: u32 = 2;
+const MAX_DECIMALS: u32 = 4;
+
+// the `?round=` a successful GET is rounded to, for the handlers rendering formats other than JSON
+#[derive(Clone, Copy)]
+pub struct Decimals(pub u32);
+
+// rounds every non-integer number in `value` to `decimals` places
+pub fn round_value(value: &mut Value, decimals: u32) {
+    match value {
+        Value::Number(n) if n.is_f64() => {
+            let factor = 10_f64.powi(decimals as i32);
+            let rounded = (n.as_f64().unwrap() * factor).round() / factor;
+            if let Some(rounded) = Number::from_f64(rounded) {
+                *n = rounded;
+            }
+        },
+        Value::Array(items) => items.iter_mut().for_each(|item| round_value(item, decimals)),
+        Value::Object(fields) => fields.values_mut().for_each(|field| round_value(field, decimals)),
+        _ => {}
+    }
+}
+
+// `?round=N` (0-4, default 2) decimal places for the numbers of successful JSON reads; done on
+// the serialized body so every read endpoint gets it the same way. With `fixed_point` every
+// successful JSON body, reads or not, is also rewritten without exponents
+pub async fn round(fixed_point: bool, mut req: Request<Body>, next: Next<Body>) -> Response {
+    let decimals = if req.method() == Method::GET {
+        let requested = req.uri().query()
+            .and_then(|q| q.split('&').find_map(|pair| pair.strip_prefix("round=")));
+        match requested.map(str::parse::<u32>) {
+            None => Some(DEFAULT_DECIMALS),
+            Some(Ok(n)) if n <= MAX_DECIMALS => Some(n),
+            Some(_) => return AppError::BadRequest(format!("round must be an integer between 0 and {}", MAX_DECIMALS)).into_response()
+        }
+    } else {
+        None
+    };
+    if let Some(decimals) = decimals {
+        req.extensions_mut().insert(Decimals(decimals));
+    }
+    if decimals.is_none() && !fixed_point {
+        return next.run(req).await;
+    }
+
+    let response = next.run(req).await;
+    let is_json = response.headers().get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"))
+        // downloads are streamed, buffering them here would defeat that
+        && !response.headers().contains_key(header::CONTENT_DISPOSITION);
+    if !response.status().is_success() || !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("Error buffering response for rounding: {}", e);
+            return Response::from_parts(parts, body::boxed(Body::empty()));
+        }
+    };
+    let Ok(mut value) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, body::boxed(Body::from(bytes)));
+    };
+    if let Some(decimals) = decimals {
+        round_value(&mut value, decimals);
+    }
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, body::boxed(Body::from(number_format::to_vec(&value, fixed_point).unwrap())))
+}
@@ -0,0 +1,113 @@
+// Versioning policy
+//
+// Every public route lives under a version prefix (`/v1/...`). Within a version, changes
+// are additive only: new endpoints, new optional query parameters and new output fields.
+// Anything that would break an existing client (renamed or removed fields, type changes
+// such as `f32` -> `f64`, different defaults, a different envelope) lands under the next
+// prefix (`/v2`) while the previous version keeps being served.
+//
+// The original unprefixed paths are kept as deprecated aliases of `/v1`; they log a
+// warning and answer with a `Deprecation` header so clients know to move.
+
+use axum::{
+    body::Body,
+    http::{HeaderValue, Method, Request, Uri},
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, patch, post, put},
+    Router,
+};
+
+use crate::{
+    analytics, audit, bulk_tag, correlation, daily_values, export, facets, favorite, goal, health, histogram, imc, indexes, lock, moving_average, plateau, profile, quality, reschedule, search, seed,
+    single_flight::{self, SingleFlight},
+    error::AppError, summary, sync, target, trend, volatility, AppState,
+};
+
+// reads dashboards fire many times at once, concurrent identical ones share a single computation
+fn expensive_reads(flights: SingleFlight) -> Router<AppState> {
+    Router::new()
+        .route("/weight/measurement/latest", get(crate::get_latest_weight_measurement))
+        .route("/weight/measurement/facets", get(facets::get_facets))
+        .route("/weight/measurement/moving-average", get(moving_average::get_moving_average))
+        .route("/weight/measurement/ma-crossovers", get(moving_average::get_crossovers))
+        .route("/weight/measurement/volatility", get(volatility::get_volatility))
+        .route("/weight/measurement/lean-trend", get(trend::get_lean_trend))
+        .route("/weight/measurement/expected-today", get(trend::get_expected_today))
+        .route("/weight/measurement/plateau", get(plateau::get_plateau))
+        .route("/weight/measurement/device-offset", get(crate::get_device_offset))
+        .route("/weight/measurement/by-weekday", get(analytics::get_by_weekday))
+        .route("/weight/measurement/daily", get(analytics::get_daily))
+        .route("/weight/measurement/daily-values", get(daily_values::get_daily_values))
+        .route("/weight/measurement/weekly", get(analytics::get_weekly))
+        .route("/weight/measurement/streak-calendar", get(analytics::get_streak_calendar))
+        .route("/weight/measurement/progress", get(analytics::get_progress))
+        .route("/weight/measurement/anomalies", get(quality::get_anomalies))
+        .route("/weight/measurement/correlation", get(correlation::get_correlation))
+        .route("/weight/measurement/histogram", get(histogram::get_histogram))
+        .route("/weight/measurement/summary", get(summary::get_summary))
+        .route_layer(middleware::from_fn(move |req, next| single_flight::share(flights.clone(), req, next)))
+}
+
+// the v1 surface, mounted both under `/v1` and at the deprecated unprefixed paths
+fn v1(flights: SingleFlight) -> Router<AppState> {
+    Router::new()
+        // `GET /` goes to `root`
+        .merge(expensive_reads(flights))
+        .route("/weight/measurement/first", get(crate::get_first_weight_measurement))
+        .route("/weight/measurement/range", get(crate::get_date_range))
+        .route("/weight/measurement/target-status", get(target::get_target_status))
+        .route("/weight/measurement/goal-burndown", get(goal::get_goal_burndown))
+        .route("/weight/measurement/to-goal", get(goal::get_to_goal))
+        .route("/weight/measurement/sync", post(sync::sync_measurements))
+        .route("/weight/measurement/bulk-tag", post(bulk_tag::bulk_tag))
+        .route("/weight/measurement/recalculate-imc", post(imc::recalculate_imc))
+        .route("/weight/measurement/healthy-range", get(imc::get_healthy_range))
+        .route("/weight/measurement/export.zip", get(export::export_zip))
+        .route("/weight/measurement/export.csv", get(export::export_csv))
+        .route("/weight/measurement/export.json", get(export::export_json))
+        .route("/weight/measurement/search", get(search::search_measurements))
+        .route("/weight/measurement/around", get(crate::get_around))
+        .route("/weight/measurement/by-ids", get(crate::get_by_ids))
+        .route("/weight/measurement/:id/neighbors", get(crate::get_neighbors))
+        .route("/weight/measurement/:id/history", get(audit::get_history))
+        .route("/weight/measurement/:id/duplicate", post(crate::duplicate_weight_measurement))
+        .route("/weight/measurement/:id/favorite", post(favorite::add_favorite).delete(favorite::remove_favorite))
+        .route("/weight/measurement/:id/date", patch(reschedule::reschedule_measurement))
+        .route("/weight/measurement/:id/lock", post(lock::lock_measurement).delete(lock::unlock_measurement))
+        .route("/weight/measurement/:id", get(crate::get_weight_measurement_id).head(crate::head_weight_measurement_id).put(crate::update_weight_measurement).delete(crate::delete_weight_measurement))
+        // `POST /users` goes to `create_user`
+        .route("/weight/measurement", get(crate::list_weight_measurements).post(crate::create_weight_measurement))
+        .route("/profile", get(profile::get_profile))
+        .route("/profile/units", put(profile::put_units))
+        .route("/profile/birth-date", put(profile::put_birth_date))
+        .route("/profile/target-band", put(profile::put_target_band))
+        .route("/profile/goal", put(profile::put_goal))
+        .route("/profile/height", put(profile::put_height))
+        .route("/profile/noise-thresholds", put(profile::put_noise_thresholds))
+}
+
+async fn deprecated_alias(req: Request<Body>, next: Next<Body>) -> Response {
+    tracing::warn!("deprecated unversioned path {} {}, use /v1{}", req.method(), req.uri().path(), req.uri().path());
+    let mut response = next.run(req).await;
+    response.headers_mut().insert("deprecation", HeaderValue::from_static("true"));
+    response
+}
+
+pub fn router() -> Router<AppState> {
+    let flights = SingleFlight::default();
+    Router::new()
+        .nest("/v1", v1(flights.clone()))
+        .merge(v1(flights).layer(middleware::from_fn(deprecated_alias)))
+        // development tooling, not part of the versioned API
+        .route("/dev/seed", post(seed::seed_measurements))
+        // probes for the orchestrator and operational tooling, not versioned either
+        .route("/ready", get(health::get_ready))
+        .route("/admin/reindex", post(indexes::reindex))
+        .fallback(unknown_route)
+}
+
+// the default fallback answers in plain text, clients expect every error as JSON
+async fn unknown_route(method: Method, uri: Uri) -> AppError {
+    AppError::UnknownRoute(format!("{} {}", method, uri.path()))
+}
@@ -0,0 +1,56 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::{bson::{doc, Regex}, options::FindOptions};
+use serde::Deserialize;
+
+use crate::{error::AppError, i18n::AcceptLanguage, units::OutputUnit, AppState, WheightMeasurementOutput};
+
+const MAX_SEARCH_RESULTS: i64 = 50;
+const MAX_QUERY_LENGTH: usize = 100;
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    q: String
+}
+
+// the query is matched literally, so every regex metacharacter is escaped
+fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\^$.|?*+()[]{}/-".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// measurements whose notes mention `q` (case-insensitive) or that are tagged exactly `q`, newest first
+pub async fn search_measurements(State(state): State<AppState>, Query(query): Query<SearchQuery>, AcceptLanguage(language): AcceptLanguage, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let q = query.q.trim();
+    if q.is_empty() || q.len() > MAX_QUERY_LENGTH {
+        return Err(AppError::BadRequest(format!("q must be between 1 and {} characters", MAX_QUERY_LENGTH)));
+    }
+
+    let notes = Regex { pattern: escape_regex(q), options: "i".to_string() };
+    let filter = doc! { "$or": [
+        { "notes": notes },
+        { "tags": q.to_lowercase() }
+    ] };
+    let options = FindOptions::builder()
+        .sort(doc! { "date": -1 })
+        .limit(MAX_SEARCH_RESULTS)
+        .build();
+    let birth_date = state.profile().await?.birth_date;
+    let mut cursor = state.timed("find", &filter, state.collection.find(filter.clone(), state.bounded(options))).await?;
+    let mut items = Vec::new();
+    while cursor.advance().await? {
+        items.push(WheightMeasurementOutput::from_entity(cursor.deserialize_current()?).with_age(birth_date).localized(language).in_unit(unit));
+    }
+
+    Ok((StatusCode::OK, Json(items)))
+}
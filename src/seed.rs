@@ -0,0 +1,78 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{Duration, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{audit::{AuditEntity, AuditOperation}, error::AppError, read_env_var, AppState, WheightMeasurementEntity, WheightMeasurementInput};
+
+const MAX_SEED_COUNT: u32 = 3650;
+// height used to derive a consistent `imc` for the generated weights
+const SEED_HEIGHT_M: f32 = 1.78;
+
+#[derive(Deserialize)]
+pub struct SeedQuery {
+    count: Option<u32>
+}
+
+#[derive(Serialize)]
+struct SeedOutput {
+    inserted: usize
+}
+
+// one measurement per day ending today, with a gentle downward trend plus scale noise
+fn generate(count: u32) -> Vec<WheightMeasurementEntity> {
+    let mut rng = rand::thread_rng();
+    let today = Utc::now();
+    (0..count)
+        .map(|i| {
+            let days_ago = count - 1 - i;
+            let progress = i as f32;
+            let wheight_kg = 92_f32 - 0.04 * progress + rng.gen_range(-0.5..0.5);
+            let fat_percentage = 28_f32 - 0.02 * progress + rng.gen_range(-0.8..0.8);
+            let muscle_kg = 38_f32 + 0.005 * progress + rng.gen_range(-0.3..0.3);
+            WheightMeasurementEntity::from_input(WheightMeasurementInput {
+                date: today - Duration::days(days_ago as i64),
+                wheight_kg,
+                imc: wheight_kg / (SEED_HEIGHT_M * SEED_HEIGHT_M),
+                fat_percentage,
+                water_percentage: 52_f32 + rng.gen_range(-1.0..1.0),
+                protein_percentage: 17_f32 + rng.gen_range(-0.5..0.5),
+                metabolism_kcal: 1850_f32 + rng.gen_range(-20.0..20.0),
+                visceral_fat_index: 11_f32 - 0.01 * progress,
+                muscle_kg,
+                bone_kg: 3.2 + rng.gen_range(-0.05..0.05),
+                metabolic_age: 38,
+                source: Some("seed".to_string()),
+                notes: None,
+                tags: Vec::new(),
+                photo_url: None
+            })
+        })
+        .collect()
+}
+
+// fills the collection with plausible history, only available when `APP_ENV=dev`
+pub async fn seed_measurements(State(state): State<AppState>, Query(query): Query<SeedQuery>) -> Result<impl IntoResponse, AppError> {
+    if read_env_var("APP_ENV", "") != "dev" {
+        return Err(AppError::Forbidden("Seeding is only available when APP_ENV=dev".to_string()));
+    }
+
+    let count = query.count.unwrap_or(90).min(MAX_SEED_COUNT);
+    let measurements = generate(count);
+    let Some(newest) = measurements.last() else {
+        return Ok((StatusCode::CREATED, Json(SeedOutput { inserted: 0 })));
+    };
+    let (id, date) = (newest._id.to_hex(), newest.date.to_chrono());
+
+    let result = state.timed("insert_many", &count, state.collection.insert_many(&measurements, None)).await?;
+    state.invalidate_latest(&id, date).await;
+    state.audit(measurements.iter().map(|m| AuditEntity::new(AuditOperation::Create, None, Some(m))).collect()).await;
+    tracing::info!("Seeded {} measurements", result.inserted_ids.len());
+
+    Ok((StatusCode::CREATED, Json(SeedOutput { inserted: result.inserted_ids.len() })))
+}
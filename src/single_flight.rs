@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::{self, Body, Bytes},
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use tokio::sync::broadcast;
+
+// a finished response, cheap to hand to every request that waited on it
+struct SharedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes
+}
+
+impl SharedResponse {
+    fn to_response(&self) -> Response {
+        let mut response = Response::new(body::boxed(Body::from(self.body.clone())));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+// `None` tells the waiters the leader failed and they should go on their own
+type Flight = broadcast::Sender<Option<Arc<SharedResponse>>>;
+
+// requests currently being computed, by path and query
+#[derive(Clone, Default)]
+pub struct SingleFlight(Arc<Mutex<HashMap<String, Flight>>>);
+
+// clears the slot when the leader finishes or is cancelled (the client went away), so
+// nobody waits on a flight that will never land
+struct Slot<'a> {
+    flights: &'a SingleFlight,
+    key: &'a str
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.flights.0.lock().unwrap().remove(self.key);
+    }
+}
+
+enum Role {
+    Leader(Flight),
+    Follower(broadcast::Receiver<Option<Arc<SharedResponse>>>)
+}
+
+// identical concurrent reads share the first one's computation; nothing outlives it, the
+// slot is cleared as soon as it completes and failures are never shared
+pub async fn share(flights: SingleFlight, req: Request<Body>, next: Next<Body>) -> Response {
+    // localized labels make the language part of what's computed
+    let language = req.headers().get(header::ACCEPT_LANGUAGE).and_then(|v| v.to_str().ok()).unwrap_or("");
+    let key = format!("{} {}", language, req.uri().path_and_query().map(|pq| pq.as_str()).unwrap_or_default());
+    let role = {
+        let mut flights = flights.0.lock().unwrap();
+        match flights.get(&key) {
+            Some(flight) => Role::Follower(flight.subscribe()),
+            None => {
+                let (flight, _) = broadcast::channel(1);
+                flights.insert(key.clone(), flight.clone());
+                Role::Leader(flight)
+            }
+        }
+    };
+
+    let flight = match role {
+        Role::Follower(mut rx) => {
+            if let Ok(Some(shared)) = rx.recv().await {
+                return shared.to_response();
+            }
+            return next.run(req).await;
+        },
+        Role::Leader(flight) => flight
+    };
+    let slot = Slot { flights: &flights, key: &key };
+
+    let response = next.run(req).await;
+    let shared = if response.status().is_success() {
+        let (parts, body) = response.into_parts();
+        match hyper::body::to_bytes(body).await {
+            Ok(body) => Ok(Arc::new(SharedResponse { status: parts.status, headers: parts.headers, body })),
+            Err(e) => {
+                tracing::error!("Error buffering shared response: {}", e);
+                Err(Response::from_parts(parts, body::boxed(Body::empty())))
+            }
+        }
+    } else {
+        Err(response)
+    };
+
+    // cleared before the broadcast so late arrivals start a fresh flight instead of missing it
+    drop(slot);
+    match shared {
+        Ok(shared) => {
+            let _ = flight.send(Some(shared.clone()));
+            shared.to_response()
+        },
+        Err(response) => {
+            let _ = flight.send(None);
+            response
+        }
+    }
+}
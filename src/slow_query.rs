@@ -0,0 +1,18 @@
+use std::future::Future;
+use std::time::Instant;
+
+use crate::{log_format::LogSummary, AppState};
+
+impl AppState {
+    // awaits a database operation and warns when it took longer than `SLOW_QUERY_MS`;
+    // `summary` (the filter or pipeline) is only formatted on that slow path
+    pub async fn timed<T, S: LogSummary + ?Sized>(&self, operation: &str, summary: &S, query: impl Future<Output = T>) -> T {
+        let started = Instant::now();
+        let result = query.await;
+        let elapsed = started.elapsed();
+        if elapsed > self.config.slow_query {
+            tracing::warn!("Slow {} took {} ms: {}", operation, elapsed.as_millis(), summary.log_summary());
+        }
+        result
+    }
+}
@@ -0,0 +1,154 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use mongodb::{bson::{self, doc, DateTime as BsonDateTime}, options::{AggregateOptions, FindOptions}};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    daily_values::{collapsed_wheights, Daily, DailyValue}, error::AppError, find_by_date, i18n::AcceptLanguage,
+    latest_measurement, timezone::Timezone, trend::{self, WheightTrend}, units::{OutputUnit, Unit}, AppState,
+    WheightMeasurementOutput,
+};
+
+// window of the summary's trend line
+const TREND_DAYS: i64 = 30;
+// profile, latest, first, stats, streak and trend
+const SECTIONS: usize = 6;
+
+#[derive(Deserialize)]
+struct StatsGroup {
+    count: i64,
+    min_wheight_kg: f64,
+    max_wheight_kg: f64,
+    avg_wheight_kg: f64
+}
+
+#[derive(Serialize)]
+struct StatsSection {
+    count: i64,
+    min_wheight_kg: f32,
+    max_wheight_kg: f32,
+    avg_wheight_kg: f32,
+    unit: Unit,
+    // only the most recent `AGG_MAX_DOCS` measurements were counted
+    approximate: bool
+}
+
+async fn stats(state: &AppState, unit: Unit) -> Result<Option<StatsSection>, AppError> {
+    let (filter, approximate) = state.capped(doc! {}).await?;
+    let pipeline = vec![doc! { "$match": filter }, doc! { "$group": {
+        "_id": null,
+        "count": { "$sum": 1 },
+        "min_wheight_kg": { "$min": "$wheight_kg" },
+        "max_wheight_kg": { "$max": "$wheight_kg" },
+        "avg_wheight_kg": { "$avg": "$wheight_kg" }
+    } }];
+    let mut cursor = state.timed("aggregate", &pipeline, state.collection.aggregate(pipeline.clone(), state.bounded(AggregateOptions::default()))).await?;
+    if !cursor.advance().await? {
+        return Ok(None);
+    }
+    let group: StatsGroup = bson::from_document(cursor.deserialize_current()?)?;
+    Ok(Some(StatsSection {
+        count: group.count,
+        min_wheight_kg: unit.convert_kg(group.min_wheight_kg as f32),
+        max_wheight_kg: unit.convert_kg(group.max_wheight_kg as f32),
+        avg_wheight_kg: unit.convert_kg(group.avg_wheight_kg as f32),
+        unit,
+        approximate
+    }))
+}
+
+#[derive(Serialize)]
+struct StreakSection {
+    // consecutive days logged up to today, or up to yesterday while today isn't logged yet
+    current_days: u32,
+    last_logged: Option<NaiveDate>
+}
+
+#[derive(Deserialize)]
+struct AggregateDateEntity {
+    date: BsonDateTime
+}
+
+// the logged days of `tz`, newest first; in UTC from the daily aggregates, so a measurement can
+// take up to one aggregation interval to count, elsewhere from the measurements themselves
+async fn logged_days(state: &AppState, tz: Tz) -> Result<Vec<NaiveDate>, AppError> {
+    if tz != Tz::UTC {
+        let days = collapsed_wheights(state, doc! {}, Daily { value: DailyValue::Last, tz }).await?;
+        return Ok(days.iter().rev().map(|p| p.day).collect());
+    }
+    let options = FindOptions::builder().sort(doc! { "date": -1 }).projection(doc! { "date": 1 }).build();
+    let mut cursor = state.timed("find", "streak", state.aggregates.clone_with_type::<AggregateDateEntity>().find(None, state.bounded(options))).await?;
+    let mut days = Vec::new();
+    while cursor.advance().await? {
+        days.push(cursor.deserialize_current()?.date.to_chrono().date_naive());
+    }
+    Ok(days)
+}
+
+async fn streak(state: &AppState, tz: Tz) -> Result<Option<StreakSection>, AppError> {
+    let days = logged_days(state, tz).await?;
+    let today = Utc::now().with_timezone(&tz).date_naive();
+    let (mut current_days, mut last_logged, mut expected) = (0, None, None);
+    for day in days {
+        last_logged = last_logged.or(Some(day));
+        let alive = match expected {
+            Some(expected) => day == expected,
+            None => day == today || day == today - Duration::days(1)
+        };
+        if !alive {
+            break;
+        }
+        current_days += 1;
+        expected = day.pred_opt();
+    }
+    Ok(Some(StreakSection { current_days, last_logged }))
+}
+
+#[derive(Serialize)]
+struct SummaryOutput {
+    // each section is null when it failed, see `failed`, or when there is no data for it
+    latest: Option<WheightMeasurementOutput>,
+    first: Option<WheightMeasurementOutput>,
+    stats: Option<StatsSection>,
+    streak: Option<StreakSection>,
+    trend: Option<WheightTrend>,
+    // sections that couldn't be computed, the rest of the summary is still served
+    failed: Vec<&'static str>
+}
+
+// a failed section is logged and nulled instead of failing the whole summary
+fn section<T>(name: &'static str, result: Result<Option<T>, AppError>, failed: &mut Vec<(&'static str, AppError)>) -> Option<T> {
+    result.unwrap_or_else(|e| {
+        tracing::error!("Summary section {} failed: {:?}", name, e);
+        failed.push((name, e));
+        None
+    })
+}
+
+// latest, first, stats, streak and trend in one response, for the dashboard's first load
+pub async fn get_summary(State(state): State<AppState>, AcceptLanguage(language): AcceptLanguage, Timezone(tz): Timezone, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let (latest, first, stats, streak, trend, profile) = tokio::join!(
+        latest_measurement(&state),
+        async { Ok(find_by_date(&state, 1).await?.map(WheightMeasurementOutput::from_entity)) },
+        stats(&state, unit),
+        streak(&state, tz),
+        async { Ok(trend::wheight_trend(&state, TREND_DAYS).await?.map(|t| t.in_unit(unit))) },
+        state.profile()
+    );
+
+    let mut failed = Vec::new();
+    let birth_date = section("profile", profile.map(Some), &mut failed).and_then(|p| p.birth_date);
+    let present = |m: WheightMeasurementOutput| m.with_age(birth_date).localized(language).in_unit(unit);
+    let latest = section("latest", latest, &mut failed).map(present);
+    let first = section("first", first, &mut failed).map(present);
+    let stats = section("stats", stats, &mut failed);
+    let streak = section("streak", streak, &mut failed);
+    let trend = section("trend", trend, &mut failed);
+    // with nothing to show, the error says more than a page of nulls
+    if failed.len() == SECTIONS {
+        return Err(failed.swap_remove(0).1);
+    }
+    let failed = failed.into_iter().map(|(name, _)| name).collect();
+    Ok((StatusCode::OK, Json(SummaryOutput { latest, first, stats, streak, trend, failed })))
+}
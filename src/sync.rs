@@ -0,0 +1,122 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use mongodb::{
+    bson::{self, doc, DateTime as BsonDateTime, Document},
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+};
+use serde::Serialize;
+
+use crate::{
+    aggregates::{self, day_start},
+    audit::{AuditEntity, AuditOperation},
+    error::AppError,
+    validation::ValidatedJson,
+    AppState, WheightMeasurementEntity, WheightMeasurementInput,
+};
+
+const MAX_SYNC_ITEMS: usize = 500;
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum SyncStatus {
+    Created,
+    Updated,
+    // the day's measurement is locked and was left as it was
+    Locked
+}
+
+#[derive(Serialize)]
+struct SyncResult {
+    id: String,
+    date: chrono::DateTime<chrono::Utc>,
+    status: SyncStatus
+}
+
+// what happened to the measurement of an item's day
+enum Upserted {
+    Created,
+    Updated(WheightMeasurementEntity),
+    Locked(WheightMeasurementEntity)
+}
+
+// replaces the unlocked measurement matching `day`, or inserts `inserted` when the day has
+// none; neither write can touch a locked one, so a lock applied meanwhile always holds
+async fn upsert_day(state: &AppState, day: &Document, set: &Document, inserted: &Document) -> Result<Upserted, AppError> {
+    let mut unlocked = day.clone();
+    unlocked.insert("is_locked", doc! { "$ne": true });
+    let before = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::Before).build();
+    let insert = FindOneAndUpdateOptions::builder().upsert(true).return_document(ReturnDocument::Before).build();
+    // a second round only when another writer created the day's measurement in between
+    for _ in 0..2 {
+        let update = doc! { "$set": set.clone(), "$inc": { "version": 1 } };
+        if let Some(existing) = state.timed("find_one_and_update", &unlocked, state.collection.find_one_and_update(unlocked.clone(), update, before.clone())).await? {
+            return Ok(Upserted::Updated(existing));
+        }
+        // only inserts, a measurement already there is left as it was
+        let update = doc! { "$setOnInsert": inserted.clone() };
+        match state.timed("find_one_and_update", day, state.collection.find_one_and_update(day.clone(), update, insert.clone())).await? {
+            None => return Ok(Upserted::Created),
+            Some(existing) if existing.is_locked => return Ok(Upserted::Locked(existing)),
+            Some(_) => continue
+        }
+    }
+    Err(AppError::Conflict("The day's measurement kept changing during the sync, retry it".to_string()))
+}
+
+// idempotent upload from an offline client: each item replaces the measurement on its
+// calendar day (UTC) or creates one, so re-sending the same batch changes nothing; a locked
+// measurement is left untouched and its item reported as `locked`, what a single write to it
+// answers with 423
+pub async fn sync_measurements(
+    State(state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<Vec<WheightMeasurementInput>>,
+) -> Result<impl IntoResponse, AppError> {
+    if payload.len() > MAX_SYNC_ITEMS {
+        return Err(AppError::Validation(format!("at most {} items can be synced at once", MAX_SYNC_ITEMS)));
+    }
+
+    let mut results = Vec::with_capacity(payload.len());
+    let mut audit = Vec::with_capacity(payload.len());
+    let mut days = Vec::with_capacity(payload.len());
+    for input in payload {
+        let mut measurement = WheightMeasurementEntity::from_input(input);
+        let date = measurement.date.to_chrono();
+        let inserted = bson::to_document(&measurement)?;
+        let mut set = inserted.clone();
+        set.remove("_id");
+        set.remove("version");
+        set.remove("is_favorite");
+        set.remove("is_locked");
+
+        let day = date.date_naive();
+        let filter = doc! { "date": {
+            "$gte": BsonDateTime::from_chrono(day_start(day)),
+            "$lt": BsonDateTime::from_chrono(day_start(day.succ_opt().unwrap()))
+        } };
+        // what the upsert wrote, rebuilt from the update rather than read back
+        let (status, before) = match upsert_day(&state, &filter, &set, &inserted).await? {
+            Upserted::Locked(existing) => {
+                results.push(SyncResult { id: existing._id.to_hex(), date: existing.date.to_chrono(), status: SyncStatus::Locked });
+                continue;
+            },
+            Upserted::Updated(existing) => {
+                measurement._id = existing._id;
+                measurement.version = existing.version + 1;
+                measurement.is_favorite = existing.is_favorite;
+                (SyncStatus::Updated, Some(existing))
+            },
+            Upserted::Created => (SyncStatus::Created, None)
+        };
+        days.push(day);
+        let id = measurement._id.to_hex();
+        audit.push(match &before {
+            Some(existing) => AuditEntity::new(AuditOperation::Update, Some(existing), Some(&measurement)),
+            None => AuditEntity::new(AuditOperation::Create, None, Some(&measurement))
+        });
+        state.invalidate_latest(&id, date).await;
+        results.push(SyncResult { id, date, status });
+    }
+    state.audit(audit).await;
+    aggregates::refresh_days(&state, &days).await;
+
+    Ok((StatusCode::OK, Json(results)))
+}
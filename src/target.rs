@@ -0,0 +1,84 @@
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::AppError, latest_measurement, units::{OutputUnit, Unit}, AppState};
+
+// maintenance range, in kg
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct TargetBand {
+    pub low_kg: f32,
+    pub high_kg: f32
+}
+
+impl TargetBand {
+    // why the band is unusable, if it is
+    pub fn problem(&self) -> Option<String> {
+        if !self.low_kg.is_finite() || !self.high_kg.is_finite() || self.low_kg <= 0_f32 || self.high_kg <= 0_f32 {
+            return Some("low and high must be positive".to_string());
+        }
+        if self.low_kg >= self.high_kg {
+            return Some(format!("low ({}) must be less than high ({})", self.low_kg, self.high_kg));
+        }
+        None
+    }
+
+    pub fn status(&self, wheight_kg: f32) -> TargetStatus {
+        let (position, distance_kg) = if wheight_kg < self.low_kg {
+            (BandPosition::Below, self.low_kg - wheight_kg)
+        } else if wheight_kg > self.high_kg {
+            (BandPosition::Above, wheight_kg - self.high_kg)
+        } else {
+            (BandPosition::Within, (wheight_kg - self.low_kg).min(self.high_kg - wheight_kg))
+        };
+        TargetStatus { position, distance_kg, low_kg: self.low_kg, high_kg: self.high_kg, unit: Unit::Kg }
+    }
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum BandPosition {
+    Below,
+    Within,
+    Above
+}
+
+#[derive(Serialize)]
+pub struct TargetStatus {
+    position: BandPosition,
+    // to the nearest band edge, from inside the band when within it
+    distance_kg: f32,
+    low_kg: f32,
+    high_kg: f32,
+    unit: Unit
+}
+
+impl TargetStatus {
+    pub fn in_unit(self, unit: Unit) -> Self {
+        TargetStatus {
+            distance_kg: unit.convert_kg(self.distance_kg),
+            low_kg: unit.convert_kg(self.low_kg),
+            high_kg: unit.convert_kg(self.high_kg),
+            unit,
+            ..self
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TargetQuery {
+    low: f32,
+    high: f32
+}
+
+// where the latest weight sits relative to the `[low, high]` band (in kg)
+pub async fn get_target_status(State(state): State<AppState>, Query(query): Query<TargetQuery>, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let band = TargetBand { low_kg: query.low, high_kg: query.high };
+    if let Some(problem) = band.problem() {
+        return Err(AppError::BadRequest(problem));
+    }
+
+    let Some(latest) = latest_measurement(&state).await? else {
+        return Err(AppError::NotFound);
+    };
+    Ok((StatusCode::OK, Json(band.status(latest.wheight_kg).in_unit(unit))))
+}
@@ -0,0 +1,82 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use crate::{error::AppError, AppState};
+
+#[derive(Deserialize)]
+struct TimezoneQuery {
+    tz: Option<String>
+}
+
+// calendar the grouping endpoints bucket days and weeks in: `?tz=`, else `DEFAULT_TIMEZONE`
+pub struct Timezone(pub Tz);
+
+#[async_trait]
+impl FromRequestParts<AppState> for Timezone {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<TimezoneQuery>::from_request_parts(parts, state).await
+            .map_err(|e| AppError::BadRequest(e.to_string()))?;
+        match query.tz {
+            Some(name) => name.parse().map(Timezone)
+                .map_err(|_| AppError::BadRequest(format!("Unknown timezone '{}', expected an IANA name such as Europe/Lisbon", name))),
+            None => Ok(Timezone(state.config.default_timezone))
+        }
+    }
+}
+
+// midnight of `day` in `tz`, or the first moment of it where a DST change skips midnight
+pub fn local_day_start(day: NaiveDate, tz: Tz) -> DateTime<Utc> {
+    let midnight = day.and_hms_opt(0, 0, 0).unwrap();
+    (0..24).find_map(|hour| tz.from_local_datetime(&(midnight + Duration::hours(hour))).earliest())
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn day_starts_at_local_midnight() {
+        assert_eq!(local_day_start(day(2024, 1, 15), Tz::UTC), utc("2024-01-15T00:00:00Z"));
+        assert_eq!(local_day_start(day(2024, 1, 15), Tz::Europe__Lisbon), utc("2024-01-15T00:00:00Z"));
+        assert_eq!(local_day_start(day(2024, 7, 1), Tz::Europe__Lisbon), utc("2024-06-30T23:00:00Z"));
+        assert_eq!(local_day_start(day(2024, 7, 1), Tz::America__Sao_Paulo), utc("2024-07-01T03:00:00Z"));
+    }
+
+    #[test]
+    fn days_around_a_dst_change_away_from_midnight_are_23_and_25_hours() {
+        let tz = Tz::Europe__Lisbon;
+        let spring = local_day_start(day(2024, 4, 1), tz) - local_day_start(day(2024, 3, 31), tz);
+        let autumn = local_day_start(day(2024, 10, 28), tz) - local_day_start(day(2024, 10, 27), tz);
+        assert_eq!((spring.num_hours(), autumn.num_hours()), (23, 25));
+    }
+
+    #[test]
+    fn a_skipped_midnight_starts_the_day_at_the_first_hour_that_exists() {
+        // Santiago springs forward from 00:00 to 01:00
+        assert_eq!(local_day_start(day(2023, 9, 3), Tz::America__Santiago), utc("2023-09-03T04:00:00Z"));
+    }
+
+    #[test]
+    fn a_repeated_midnight_starts_the_day_at_its_first_occurrence() {
+        // Havana falls back from 01:00 to 00:00, so midnight comes twice
+        assert_eq!(local_day_start(day(2023, 11, 5), Tz::America__Havana), utc("2023-11-05T04:00:00Z"));
+    }
+}
@@ -0,0 +1,304 @@
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Duration, Utc};
+use mongodb::{bson::{doc, DateTime as BsonDateTime}, options::FindOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::{daily_values::{collapsed_wheights, Collapse, Daily}, derive_fat_kg, error::AppError, or_derived, units::{OutputUnit, Unit}, AppState};
+
+const DEFAULT_DAYS: i64 = 90;
+const MAX_DAYS: i64 = 3650;
+// slower than this either way a series counts as stable
+pub const STABLE_KG_PER_WEEK: f64 = 0.1;
+
+// least-squares line through `(x, y)` points
+#[derive(Clone, Copy, Debug)]
+pub struct LinearFit {
+    pub slope: f64,
+    pub intercept: f64
+}
+
+impl LinearFit {
+    pub fn at(&self, x: f64) -> f64 {
+        self.intercept + self.slope * x
+    }
+}
+
+// `None` with fewer than two points or when every `x` is the same
+pub fn linear_fit(points: &[(f64, f64)]) -> Option<LinearFit> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    if variance == 0_f64 {
+        return None;
+    }
+    let slope = covariance / variance;
+    Some(LinearFit { slope, intercept: mean_y - slope * mean_x })
+}
+
+// fractional days since `origin`, the `x` of a dated point
+pub fn days_since(origin: DateTime<Utc>, date: DateTime<Utc>) -> f64 {
+    (date - origin).num_milliseconds() as f64 / 86_400_000_f64
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrendDirection {
+    Losing,
+    Stable,
+    Gaining
+}
+
+pub fn classify(kg_per_week: f64) -> TrendDirection {
+    if kg_per_week <= -STABLE_KG_PER_WEEK {
+        TrendDirection::Losing
+    } else if kg_per_week >= STABLE_KG_PER_WEEK {
+        TrendDirection::Gaining
+    } else {
+        TrendDirection::Stable
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TrendQuery {
+    // how far back the series goes
+    days: Option<i64>
+}
+
+#[derive(Deserialize)]
+struct LeanEntity {
+    date: BsonDateTime,
+    wheight_kg: f32,
+    fat_percentage: f32,
+    #[serde(default)]
+    fat_kg: f32
+}
+
+#[derive(Serialize)]
+struct LeanPoint {
+    date: DateTime<Utc>,
+    lean_mass_kg: f32
+}
+
+#[derive(Serialize)]
+struct LeanTrendOutput {
+    // null (and `direction` too) with fewer than two points
+    slope_kg_per_week: Option<f32>,
+    direction: Option<TrendDirection>,
+    // the line's value at the latest point, less noisy than that point itself
+    trend_lean_mass_kg: Option<f32>,
+    // measurements without body fat are left out
+    skipped: usize,
+    unit: Unit,
+    points: Vec<LeanPoint>
+}
+
+// lean mass (`wheight_kg - fat_kg`) over the last `days` and the line fitted through it
+pub async fn get_lean_trend(State(state): State<AppState>, Query(query): Query<TrendQuery>, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let days = query.days.unwrap_or(DEFAULT_DAYS);
+    if !(1..=MAX_DAYS).contains(&days) {
+        return Err(AppError::BadRequest(format!("days must be between 1 and {}", MAX_DAYS)));
+    }
+
+    let since = Utc::now() - Duration::days(days);
+    let filter = doc! { "date": { "$gte": BsonDateTime::from_chrono(since) } };
+    let options = FindOptions::builder()
+        .sort(doc! { "date": 1 })
+        .projection(doc! { "date": 1, "wheight_kg": 1, "fat_percentage": 1, "fat_kg": 1 })
+        .build();
+    let mut cursor = state.timed("find", &filter, state.collection.clone_with_type::<LeanEntity>().find(filter.clone(), state.bounded(options))).await?;
+    let (mut points, mut skipped) = (Vec::new(), 0);
+    while cursor.advance().await? {
+        let m = cursor.deserialize_current()?;
+        // scales without impedance report no body fat at all
+        if m.fat_percentage <= 0_f32 {
+            skipped += 1;
+            continue;
+        }
+        let fat_kg = or_derived(m.fat_kg, || derive_fat_kg(m.wheight_kg, m.fat_percentage));
+        points.push(LeanPoint { date: m.date.to_chrono(), lean_mass_kg: m.wheight_kg - fat_kg });
+    }
+
+    let xy: Vec<(f64, f64)> = match points.first() {
+        Some(first) => points.iter().map(|p| (days_since(first.date, p.date), p.lean_mass_kg as f64)).collect(),
+        None => Vec::new()
+    };
+    let fit = linear_fit(&xy);
+    let kg_per_week = fit.map(|f| f.slope * 7_f64);
+    let trend_lean_mass_kg = fit.zip(xy.last()).map(|(f, (x, _))| unit.convert_kg(f.at(*x) as f32));
+    Ok((StatusCode::OK, Json(LeanTrendOutput {
+        slope_kg_per_week: kg_per_week.map(|s| unit.convert_kg(s as f32)),
+        direction: kg_per_week.map(classify),
+        trend_lean_mass_kg,
+        skipped,
+        unit,
+        points: points.into_iter().map(|p| LeanPoint { lean_mass_kg: unit.convert_kg(p.lean_mass_kg), ..p }).collect()
+    })))
+}
+
+// the nowcast looks at this many recent days by default, a longer window lags behind changes
+const DEFAULT_NOWCAST_DAYS: i64 = 14;
+const MAX_NOWCAST_DAYS: i64 = 90;
+// `z` of a 95% interval
+const CONFIDENCE_Z: f64 = 1.96;
+
+#[derive(Deserialize)]
+struct WheightPointEntity {
+    date: BsonDateTime,
+    wheight_kg: f32
+}
+
+#[derive(Serialize)]
+struct ExpectedTodayOutput {
+    // always true, this is an extrapolation and not a measurement
+    prediction: bool,
+    date: DateTime<Utc>,
+    wheight_kg: f32,
+    // 95% interval from the spread of the recent points around their line
+    low_wheight_kg: f32,
+    high_wheight_kg: f32,
+    slope_kg_per_week: f32,
+    based_on_count: usize,
+    last_measured_at: DateTime<Utc>,
+    unit: Unit
+}
+
+// `(date, wheight_kg)` of every measurement since `since`, oldest first
+pub async fn wheight_points(state: &AppState, since: DateTime<Utc>) -> mongodb::error::Result<Vec<(DateTime<Utc>, f64)>> {
+    let filter = doc! { "date": { "$gte": BsonDateTime::from_chrono(since) } };
+    let options = FindOptions::builder()
+        .sort(doc! { "date": 1 })
+        .projection(doc! { "date": 1, "wheight_kg": 1 })
+        .build();
+    let mut cursor = state.timed("find", &filter, state.collection.clone_with_type::<WheightPointEntity>().find(filter.clone(), state.bounded(options))).await?;
+    let mut points = Vec::new();
+    while cursor.advance().await? {
+        let m = cursor.deserialize_current()?;
+        points.push((m.date.to_chrono(), m.wheight_kg as f64));
+    }
+    Ok(points)
+}
+
+// `wheight_points`, or with `daily` one point per day
+pub async fn wheight_series(state: &AppState, since: DateTime<Utc>, daily: Option<Daily>) -> mongodb::error::Result<Vec<(DateTime<Utc>, f64)>> {
+    let Some(daily) = daily else {
+        return wheight_points(state, since).await;
+    };
+    let filter = doc! { "date": { "$gte": BsonDateTime::from_chrono(since) } };
+    Ok(collapsed_wheights(state, filter, daily).await?.into_iter().map(|p| (p.date, p.wheight_kg)).collect())
+}
+
+#[derive(Serialize)]
+pub struct WheightTrend {
+    pub slope_kg_per_week: f32,
+    pub direction: TrendDirection,
+    pub based_on_count: usize
+}
+
+impl WheightTrend {
+    pub fn in_unit(self, unit: Unit) -> Self {
+        WheightTrend { slope_kg_per_week: unit.convert_kg(self.slope_kg_per_week), ..self }
+    }
+}
+
+// line through the weights of the last `days`, `None` with fewer than two measurements
+pub async fn wheight_trend(state: &AppState, days: i64) -> mongodb::error::Result<Option<WheightTrend>> {
+    let points = wheight_points(state, Utc::now() - Duration::days(days)).await?;
+    let Some(&(first, _)) = points.first() else {
+        return Ok(None);
+    };
+    let xy: Vec<(f64, f64)> = points.iter().map(|(date, kg)| (days_since(first, *date), *kg)).collect();
+    Ok(linear_fit(&xy).map(|fit| WheightTrend {
+        slope_kg_per_week: (fit.slope * 7_f64) as f32,
+        direction: classify(fit.slope * 7_f64),
+        based_on_count: xy.len()
+    }))
+}
+
+// standard deviation of the points around `fit`; `None` below three points, two always sit
+// exactly on their line
+fn residual_spread(points: &[(f64, f64)], fit: &LinearFit) -> Option<f64> {
+    if points.len() < 3 {
+        return None;
+    }
+    let residuals: f64 = points.iter().map(|(x, y)| (y - fit.at(*x)).powi(2)).sum();
+    Some((residuals / (points.len() - 2) as f64).sqrt())
+}
+
+// what today's weight should be: the last measurement moved along the recent trend to now
+pub async fn get_expected_today(State(state): State<AppState>, Query(query): Query<TrendQuery>, collapse: Collapse, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let days = query.days.unwrap_or(DEFAULT_NOWCAST_DAYS);
+    if !(1..=MAX_NOWCAST_DAYS).contains(&days) {
+        return Err(AppError::BadRequest(format!("days must be between 1 and {}", MAX_NOWCAST_DAYS)));
+    }
+
+    let now = Utc::now();
+    let points = wheight_series(&state, now - Duration::days(days), collapse.requested()).await?;
+
+    let (Some(&(first, _)), Some(&(last, last_kg))) = (points.first(), points.last()) else {
+        return Err(AppError::NotFound);
+    };
+    let xy: Vec<(f64, f64)> = points.iter().map(|(date, kg)| (days_since(first, *date), *kg)).collect();
+    let Some((fit, spread)) = linear_fit(&xy).and_then(|fit| Some((fit, residual_spread(&xy, &fit)?))) else {
+        return Err(AppError::NotFound);
+    };
+
+    let expected = last_kg + fit.slope * days_since(last, now);
+    let kg = |v: f64| unit.convert_kg(v as f32);
+    Ok((StatusCode::OK, Json(ExpectedTodayOutput {
+        prediction: true,
+        date: now,
+        wheight_kg: kg(expected),
+        low_wheight_kg: kg(expected - CONFIDENCE_Z * spread),
+        high_wheight_kg: kg(expected + CONFIDENCE_Z * spread),
+        slope_kg_per_week: kg(fit.slope * 7_f64),
+        based_on_count: xy.len(),
+        last_measured_at: last,
+        unit
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_needs_two_distinct_xs() {
+        assert!(linear_fit(&[]).is_none());
+        assert!(linear_fit(&[(0.0, 80.0)]).is_none());
+        assert!(linear_fit(&[(3.0, 80.0), (3.0, 81.0)]).is_none());
+    }
+
+    #[test]
+    fn fit_goes_through_points_on_a_line() {
+        let fit = linear_fit(&[(0.0, 80.0), (1.0, 79.9), (2.0, 79.8)]).unwrap();
+        assert!((fit.slope + 0.1).abs() < 1e-9);
+        assert!((fit.intercept - 80.0).abs() < 1e-9);
+        assert!((fit.at(10.0) - 79.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fit_of_a_constant_series_is_flat() {
+        let fit = linear_fit(&[(0.0, 80.0), (1.0, 80.0), (5.0, 80.0)]).unwrap();
+        assert_eq!(fit.slope, 0.0);
+        assert!(classify(fit.slope * 7.0) == TrendDirection::Stable);
+    }
+
+    #[test]
+    fn spread_needs_a_third_point_and_is_zero_on_the_line() {
+        let two = [(0.0, 80.0), (1.0, 81.0)];
+        assert!(residual_spread(&two, &linear_fit(&two).unwrap()).is_none());
+
+        let on_line = [(0.0, 80.0), (1.0, 81.0), (2.0, 82.0)];
+        assert!(residual_spread(&on_line, &linear_fit(&on_line).unwrap()).unwrap() < 1e-9);
+
+        // residuals of -1/3, 2/3 and -1/3 around the fitted line, with one degree of freedom
+        let noisy = [(0.0, 80.0), (1.0, 81.0), (2.0, 80.0)];
+        let spread = residual_spread(&noisy, &linear_fit(&noisy).unwrap()).unwrap();
+        assert!((spread - (2.0_f64 / 3.0).sqrt()).abs() < 1e-9);
+    }
+}
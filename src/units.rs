@@ -0,0 +1,58 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::AppError, AppState};
+
+const LB_PER_KG: f32 = 2.204_622_6;
+
+// unit the mass fields (`*_kg`) are expressed in; storage is always kg
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Unit {
+    #[default]
+    Kg,
+    Lb
+}
+
+impl Unit {
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "kg" => Ok(Unit::Kg),
+            "lb" => Ok(Unit::Lb),
+            other => Err(AppError::BadRequest(format!("Unknown unit '{}', expected kg or lb", other)))
+        }
+    }
+
+    pub fn convert_kg(self, kg: f32) -> f32 {
+        match self {
+            Unit::Kg => kg,
+            Unit::Lb => kg * LB_PER_KG
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct UnitQuery {
+    unit: Option<String>
+}
+
+// output unit for a read: an explicit `?unit=` wins, otherwise the profile preference
+pub struct OutputUnit(pub Unit);
+
+#[async_trait]
+impl FromRequestParts<AppState> for OutputUnit {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<UnitQuery>::from_request_parts(parts, state).await
+            .map_err(|e| AppError::BadRequest(e.to_string()))?;
+        match query.unit {
+            Some(unit) => Ok(OutputUnit(Unit::parse(&unit)?)),
+            None => Ok(OutputUnit(state.profile().await?.units))
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use axum::{
+    async_trait,
+    body::{Bytes, HttpBody},
+    extract::{FromRequest, FromRequestParts},
+    http::{header, Request},
+    BoxError,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{error::AppError, percent_format::PercentFormat};
+
+// checks a deserialized body can be acted on, failures answer with a 422; bodies with
+// nothing beyond their types to check can use the default
+pub trait Validate {
+    fn validate(&self) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    // brings percentages sent as fractions to percents before they are validated, bodies
+    // without percentages ignore it
+    fn scale_percentages(&mut self, _factor: f32) {}
+}
+
+// something odd about an accepted body, reported alongside the result instead of rejecting it
+#[derive(Serialize)]
+pub struct Warning {
+    pub field: &'static str,
+    pub message: String
+}
+
+// collects a body's invalid fields so they are reported together; a field keeps its first problem
+#[derive(Default)]
+pub struct FieldErrors(HashMap<String, String>);
+
+impl FieldErrors {
+    pub fn add(&mut self, field: &str, message: String) {
+        self.0.entry(field.to_string()).or_insert(message);
+    }
+
+    pub fn has(&self, field: &str) -> bool {
+        self.0.contains_key(field)
+    }
+
+    pub fn into_result(self) -> Result<(), AppError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::InvalidFields(self.0))
+        }
+    }
+}
+
+// `Json<T>` that only reaches the handler once `T` passed its `Validate` checks; every
+// body problem is reported in the usual `ErrorResponse` shape
+pub struct ValidatedJson<T>(pub T);
+
+fn is_json(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+// serde_json reports 1-based line/column, clients with a raw buffer want the byte offset
+fn byte_offset(body: &[u8], line: usize, column: usize) -> usize {
+    let line_start: usize = body.split(|b| *b == b'\n').take(line.saturating_sub(1)).map(|l| l.len() + 1).sum();
+    (line_start + column.saturating_sub(1)).min(body.len())
+}
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let (mut parts, body) = req.into_parts();
+        let percent_format = PercentFormat::from_request_parts(&mut parts, state).await?;
+        let req = Request::from_parts(parts, body);
+        let content_type = req.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("");
+        if !is_json(content_type) {
+            return Err(AppError::BadRequest("Expected a request with `Content-Type: application/json`".to_string()));
+        }
+        let body = Bytes::from_request(req, state).await
+            .map_err(|e| AppError::BadRequest(format!("Failed to read the request body: {}", e)))?;
+        if body.iter().all(u8::is_ascii_whitespace) {
+            return Err(AppError::BadRequest("Request body is empty, expected a JSON document".to_string()));
+        }
+
+        let mut value: T = serde_json::from_slice(&body).map_err(|e| {
+            let offset = byte_offset(&body, e.line(), e.column());
+            match e.classify() {
+                // well-formed JSON with the wrong shape is a validation failure like any other
+                serde_json::error::Category::Data => AppError::Validation(format!("Invalid body at byte {}: {}", offset, e)),
+                _ => AppError::BadRequest(format!("Malformed JSON at byte {}: {}", offset, e))
+            }
+        })?;
+        if percent_format != PercentFormat::Percent {
+            value.scale_percentages(percent_format.factor());
+        }
+        value.validate()?;
+        Ok(ValidatedJson(value))
+    }
+}
+
+// a batch is valid when every item is, failures name the offending index; the invalid fields
+// of every item are reported together, under `<index>.<field>`
+impl<T: Validate> Validate for Vec<T> {
+    fn validate(&self) -> Result<(), AppError> {
+        let mut errors = FieldErrors::default();
+        for (i, item) in self.iter().enumerate() {
+            match item.validate() {
+                Ok(()) => {},
+                Err(AppError::InvalidFields(fields)) => for (field, message) in fields {
+                    errors.add(&format!("{}.{}", i, field), format!("item {}: {}", i, message));
+                },
+                Err(AppError::Validation(message)) => return Err(AppError::Validation(format!("item {}: {}", i, message))),
+                Err(other) => return Err(other)
+            }
+        }
+        errors.into_result()
+    }
+
+    fn scale_percentages(&mut self, factor: f32) {
+        self.iter_mut().for_each(|item| item.scale_percentages(factor));
+    }
+}
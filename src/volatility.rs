@@ -0,0 +1,156 @@
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use mongodb::{bson::{doc, DateTime as BsonDateTime}, options::FindOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::AppError, units::{OutputUnit, Unit}, AppState};
+
+const DEFAULT_WINDOW: usize = 14;
+const MAX_WINDOW: usize = 90;
+
+#[derive(Deserialize)]
+struct WheightPointEntity {
+    date: BsonDateTime,
+    wheight_kg: f32
+}
+
+#[derive(Deserialize)]
+pub struct VolatilityQuery {
+    window: Option<usize>
+}
+
+#[derive(Serialize)]
+struct VolatilityPoint {
+    date: DateTime<Utc>,
+    // sample standard deviation, null until the window holds two measurements
+    wheight_kg_stddev: Option<f32>,
+    // measurements in the window, fewer than `window` at the start of the history
+    count: usize,
+    unit: Unit
+}
+
+// Welford's running mean/variance, with removal so it can slide over a window without the
+// cancellation of the naive sum-of-squares formula
+#[derive(Default)]
+struct RollingVariance {
+    count: usize,
+    mean: f64,
+    m2: f64
+}
+
+impl RollingVariance {
+    fn push(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    fn pop(&mut self, x: f64) {
+        if self.count <= 1 {
+            *self = RollingVariance::default();
+            return;
+        }
+        self.count -= 1;
+        let delta = x - self.mean;
+        self.mean -= delta / self.count as f64;
+        self.m2 -= delta * (x - self.mean);
+    }
+
+    fn stddev(&self) -> Option<f64> {
+        // rounding can leave a tiny negative `m2` for a constant window
+        (self.count >= 2).then(|| (self.m2.max(0_f64) / (self.count - 1) as f64).sqrt())
+    }
+}
+
+// standard deviation of `wheight_kg` over the trailing `window` measurements, one point per measurement
+pub async fn get_volatility(State(state): State<AppState>, Query(query): Query<VolatilityQuery>, OutputUnit(unit): OutputUnit) -> Result<impl IntoResponse, AppError> {
+    let window = query.window.unwrap_or(DEFAULT_WINDOW);
+    if !(2..=MAX_WINDOW).contains(&window) {
+        return Err(AppError::BadRequest(format!("window must be between 2 and {}", MAX_WINDOW)));
+    }
+
+    let options = FindOptions::builder()
+        .sort(doc! { "date": 1 })
+        .projection(doc! { "date": 1, "wheight_kg": 1 })
+        .build();
+    let mut cursor = state.timed("find", "all", state.collection.clone_with_type::<WheightPointEntity>().find(None, state.bounded(options))).await?;
+    let mut weights = Vec::new();
+    while cursor.advance().await? {
+        weights.push(cursor.deserialize_current()?);
+    }
+
+    let mut rolling = RollingVariance::default();
+    let points: Vec<VolatilityPoint> = (0..weights.len())
+        .map(|i| {
+            rolling.push(weights[i].wheight_kg as f64);
+            if i >= window {
+                rolling.pop(weights[i - window].wheight_kg as f64);
+            }
+            VolatilityPoint {
+                date: weights[i].date.to_chrono(),
+                wheight_kg_stddev: rolling.stddev().map(|s| unit.convert_kg(s as f32)),
+                count: rolling.count,
+                unit
+            }
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(points)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the two-pass sample standard deviation, to check the rolling one against
+    fn stddev(values: &[f64]) -> f64 {
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64).sqrt()
+    }
+
+    #[test]
+    fn needs_two_values() {
+        let mut rolling = RollingVariance::default();
+        assert!(rolling.stddev().is_none());
+        rolling.push(80.0);
+        assert!(rolling.stddev().is_none());
+        rolling.push(82.0);
+        assert!((rolling.stddev().unwrap() - 2_f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sliding_matches_the_window_recomputed() {
+        let values = [80.0, 80.4, 79.8, 81.2, 80.9, 79.5, 80.1];
+        let window = 3;
+        let mut rolling = RollingVariance::default();
+        for i in 0..values.len() {
+            rolling.push(values[i]);
+            if i >= window {
+                rolling.pop(values[i - window]);
+            }
+            if i + 1 >= window {
+                assert_eq!(rolling.count, window);
+                assert!((rolling.stddev().unwrap() - stddev(&values[i + 1 - window..=i])).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn a_constant_window_has_no_spread() {
+        let mut rolling = RollingVariance::default();
+        for value in [80.1, 80.1, 80.1, 80.1] {
+            rolling.push(value);
+        }
+        rolling.pop(80.1);
+        assert_eq!(rolling.stddev(), Some(0.0));
+    }
+
+    #[test]
+    fn popping_the_last_value_resets() {
+        let mut rolling = RollingVariance::default();
+        rolling.push(80.0);
+        rolling.pop(80.0);
+        assert_eq!((rolling.count, rolling.mean, rolling.m2), (0, 0.0, 0.0));
+    }
+}